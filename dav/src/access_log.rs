@@ -0,0 +1,146 @@
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, BoxBody, Bytes, HttpBody},
+    extract::ConnectInfo,
+    headers::{authorization::Basic, Authorization, HeaderMapExt},
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::TryStreamExt;
+
+use crate::{auth::Identity, digest::digest_user, share::redact_token};
+
+/// Logs every request to the `dav::access` target once its response body has
+/// been sent, with the user it was authenticated as and the bytes received
+/// and sent. Credentials are never logged and share tokens in paths are
+/// redacted.
+pub(crate) async fn access_log_middleware(req: Request<Body>, next: Next<Body>) -> Response {
+    let start = Instant::now();
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let bytes_in = Arc::new(AtomicU64::new(0));
+    let entry = AccessLogEntry {
+        client,
+        user: None,
+        method: req.method().to_string(),
+        path: redact_token(req.uri().path()).into_owned(),
+        destination: req
+            .headers()
+            .get("Destination")
+            .and_then(|d| d.to_str().ok())
+            .map(|d| redact_token(d).into_owned()),
+        status: StatusCode::OK,
+        bytes_in: bytes_in.clone(),
+        bytes_out: 0,
+        start,
+    };
+
+    let req = req.map(|body| {
+        Body::wrap_stream(body.inspect_ok(move |chunk| {
+            bytes_in.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }))
+    });
+    let resp = next.run(req).await;
+    let entry = AccessLogEntry {
+        user: resp
+            .extensions()
+            .get::<Identity>()
+            .map(|identity| identity.user.clone()),
+        status: resp.status(),
+        ..entry
+    };
+    resp.map(|inner| axum::body::boxed(LoggedBody { inner, entry }))
+}
+
+/// The user name the client claims to be, whether or not it is authorized.
+pub(crate) fn request_user(headers: &HeaderMap) -> Option<String> {
+    headers
+        .typed_get::<Authorization<Basic>>()
+        .map(|auth| auth.username().to_string())
+        .or_else(|| digest_user(headers))
+}
+
+#[derive(Debug)]
+struct AccessLogEntry {
+    client: Option<String>,
+    user: Option<String>,
+    method: String,
+    path: String,
+    destination: Option<String>,
+    status: StatusCode,
+    /// Counted as the request body is read, which may go on after the
+    /// response started.
+    bytes_in: Arc<AtomicU64>,
+    bytes_out: u64,
+    start: Instant,
+}
+
+/// Response body that counts the bytes sent and writes the access log entry
+/// when it is dropped, i.e. when the response is done or the client is gone.
+struct LoggedBody {
+    inner: BoxBody,
+    entry: AccessLogEntry,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.entry.bytes_out += chunk.len() as u64;
+        }
+        poll
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        let e = &self.entry;
+        tracing::info!(
+            target: "dav::access",
+            client = e.client.as_deref().unwrap_or("-"),
+            user = e.user.as_deref().unwrap_or("-"),
+            method = %e.method,
+            path = %e.path,
+            destination = e.destination.as_deref().unwrap_or("-"),
+            status = e.status.as_u16(),
+            bytes_in = e.bytes_in.load(Ordering::Relaxed),
+            bytes_out = e.bytes_out,
+            duration_ms = e.start.elapsed().as_secs_f64() * 1000.0,
+            "request"
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, PasswordHash, SaltString},
+    Argon2, PasswordHasher, PasswordVerifier,
+};
+use axum::{
+    extract::{ConnectInfo, State},
+    headers::{authorization::Basic, Authorization, HeaderMapExt},
+    http::{header, request::Parts, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{AppendHeaders, IntoResponse, Response},
+    Extension,
+};
+use futures_util::future::BoxFuture;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    access_log::request_user,
+    client_cert::ClientCert,
+    digest::DigestAlgorithm,
+    htpasswd,
+    lockout::{BanTarget, LockoutPolicy, Lockouts},
+    share::{ShareAuth, ShareGrant, SharePrefix},
+    REDACTED,
+};
+
+/// [`Identity::user`] of requests let in by [`DavConfig::anonymous_read`](crate::DavConfig::anonymous_read).
+pub const ANONYMOUS: &str = "anonymous";
+
+/// How long a successful password verification is remembered, so clients
+/// that send their credentials with every request are not hashed each time.
+const VERIFIED_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Hash `password` with argon2 into a PHC string usable with
+/// [`Account::from_hash`].
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("failed to hash password: {}", e))
+}
+
+/// Check `password` against an argon2 PHC string, a bcrypt hash or one of
+/// the weaker htpasswd hashes. All compare in constant time.
+fn verify_password(password: &str, hash: &str) -> bool {
+    if is_bcrypt(hash) {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    } else if htpasswd::is_htpasswd_hash(hash) {
+        htpasswd::verify(password, hash)
+    } else {
+        PasswordHash::new(hash)
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+/// How clients prove who they are.
+#[derive(Clone, Default)]
+pub enum AuthScheme {
+    /// HTTP Basic authentication against the configured accounts; the
+    /// password is sent with every request, so it should only be used over
+    /// TLS.
+    #[default]
+    Basic,
+    /// HTTP Digest authentication (RFC 7616) against the configured
+    /// accounts. Every account needs [`Account::digest`] credentials for the
+    /// configured realm.
+    Digest,
+    /// Authentication by the embedder. The configured accounts are not
+    /// used; requests without an identity root are served from the
+    /// [`DavConfig`](crate::DavConfig) root.
+    Custom(Arc<dyn Authenticator>),
+}
+
+impl std::fmt::Debug for AuthScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthScheme::Basic => write!(f, "Basic"),
+            AuthScheme::Digest => write!(f, "Digest"),
+            AuthScheme::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl PartialEq for AuthScheme {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AuthScheme::Basic, AuthScheme::Basic) => true,
+            (AuthScheme::Digest, AuthScheme::Digest) => true,
+            (AuthScheme::Custom(a), AuthScheme::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// What an account may do with the files it is served.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Permission {
+    /// Browse and download.
+    ReadOnly,
+    /// Browse, download, create folders and upload new files, but not
+    /// overwrite, delete, move, copy or change the properties of anything.
+    UploadOnly,
+    #[default]
+    Full,
+}
+
+impl Permission {
+    /// Whether requests with `method` are allowed at all. Upload-only
+    /// accounts are additionally kept from overwriting files when serving.
+    pub(crate) fn allows(self, method: &Method) -> bool {
+        let read = [Method::GET, Method::HEAD, Method::OPTIONS];
+        match self {
+            Permission::Full => true,
+            _ if read.contains(method) || method.as_str() == "PROPFIND" => true,
+            Permission::UploadOnly => {
+                *method == Method::PUT || ["MKCOL", "LOCK", "UNLOCK"].contains(&method.as_str())
+            }
+            Permission::ReadOnly => false,
+        }
+    }
+}
+
+/// A user that may log in to the server. Only hashes of the password are
+/// kept.
+#[derive(Clone, PartialEq)]
+pub struct Account {
+    pub(crate) user: String,
+    pub(crate) password_hash: String,
+    pub(crate) digest_ha1: HashMap<DigestAlgorithm, String>,
+    pub(crate) root: Option<String>,
+    pub(crate) permission: Permission,
+    pub(crate) client_cert: Option<String>,
+}
+
+impl std::fmt::Debug for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Account")
+            .field("user", &self.user)
+            .field("password_hash", &REDACTED)
+            .field("digest_ha1", &REDACTED)
+            .field("root", &self.root)
+            .field("permission", &self.permission)
+            .field("client_cert", &self.client_cert)
+            .finish()
+    }
+}
+
+impl Account {
+    /// An account served from the [`DavConfig`](crate::DavConfig) root. The
+    /// password is hashed with argon2 right away.
+    pub fn new(user: String, password: String) -> Self {
+        let password_hash = hash_password(&password).expect("argon2 with default params");
+        Account {
+            user,
+            password_hash,
+            digest_ha1: HashMap::new(),
+            root: None,
+            permission: Permission::Full,
+            client_cert: None,
+        }
+    }
+
+    /// An account whose password is already hashed, either as an argon2 PHC
+    /// string or as a bcrypt hash. The SHA1 and APR1-MD5 hashes of htpasswd
+    /// files are accepted as well, but are much easier to crack.
+    pub fn from_hash(user: String, password_hash: String) -> Result<Self> {
+        if htpasswd::is_htpasswd_hash(&password_hash) {
+            if !htpasswd::is_valid_hash(&password_hash) {
+                return Err(anyhow::anyhow!("invalid htpasswd hash of {}", user));
+            }
+        } else if !is_bcrypt(&password_hash) {
+            PasswordHash::new(&password_hash)
+                .map_err(|e| anyhow::anyhow!("invalid password hash of {}: {}", user, e))?;
+        } else if password_hash.parse::<bcrypt::HashParts>().is_err() {
+            return Err(anyhow::anyhow!("invalid bcrypt hash of {}", user));
+        }
+
+        Ok(Account {
+            user,
+            password_hash,
+            digest_ha1: HashMap::new(),
+            root: None,
+            permission: Permission::Full,
+            client_cert: None,
+        })
+    }
+
+    /// An account without a password that only logs in with a client
+    /// certificate, see [`Account::client_cert`].
+    pub fn client_cert_only(user: String, name: String) -> Self {
+        Account {
+            user,
+            password_hash: String::new(),
+            digest_ha1: HashMap::new(),
+            root: None,
+            permission: Permission::Full,
+            client_cert: Some(name),
+        }
+    }
+
+    /// Allow this account to log in with [`AuthScheme::Digest`] and any
+    /// supported algorithm. `realm` must be the configured one.
+    pub fn digest(mut self, realm: &str, password: &str) -> Self {
+        for algorithm in [DigestAlgorithm::Md5, DigestAlgorithm::Sha256] {
+            let ha1 = algorithm.ha1(&self.user, realm, password);
+            self.digest_ha1.insert(algorithm, ha1);
+        }
+        self
+    }
+
+    /// Allow this account to log in with [`AuthScheme::Digest`] and
+    /// `algorithm`, given [`DigestAlgorithm::ha1`] of its password in the
+    /// configured realm.
+    pub fn digest_ha1(mut self, algorithm: DigestAlgorithm, ha1: String) -> Self {
+        self.digest_ha1.insert(algorithm, ha1.to_ascii_lowercase());
+        self
+    }
+
+    /// Serve this account from its own folder instead of the
+    /// [`DavConfig`](crate::DavConfig) root.
+    pub fn root(mut self, root: String) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    /// Log clients in to this account without asking for the password when
+    /// they present a certificate signed by the client CA of the
+    /// [`TlsConfig`](crate::TlsConfig) whose subject common name or a DNS or
+    /// email alternative name is `name`.
+    pub fn client_cert(mut self, name: String) -> Self {
+        self.client_cert = Some(name);
+        self
+    }
+
+    /// Restrict what this account may do, [`Permission::Full`] by default.
+    pub fn permission(mut self, permission: Permission) -> Self {
+        self.permission = permission;
+        self
+    }
+}
+
+/// Who a request has been authenticated as. Added to the request extensions
+/// for the layers after authentication.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub user: String,
+    /// Folder to serve instead of the [`DavConfig`](crate::DavConfig) root.
+    pub root: Option<String>,
+    pub permission: Permission,
+}
+
+impl From<&Account> for Identity {
+    fn from(account: &Account) -> Self {
+        Identity {
+            user: account.user.clone(),
+            root: account.root.clone(),
+            permission: account.permission,
+        }
+    }
+}
+
+/// `WWW-Authenticate` values sent along with `401 Unauthorized` when a
+/// request could not be authenticated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Challenge(pub Vec<HeaderValue>);
+
+/// Decides who a request comes from, see [`AuthScheme::Custom`].
+pub trait Authenticator: Send + Sync {
+    /// The identity of the client sending the request `parts` describe, or
+    /// the challenge to answer it with.
+    fn authenticate<'a>(&'a self, parts: &'a Parts) -> BoxFuture<'a, Result<Identity, Challenge>>;
+}
+
+/// The accounts of a router together with the credentials verified lately.
+#[derive(Debug)]
+struct Accounts {
+    accounts: Vec<Account>,
+    /// Keyed hash of `user:password` to the time it was verified.
+    verified: Mutex<HashMap<[u8; 32], Instant>>,
+    cache_key: [u8; 32],
+}
+
+/// Verified against unknown users so they take as long as known ones. It is
+/// hashed once, not for every router.
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| hash_password("").expect("argon2 with default params"))
+}
+
+impl Accounts {
+    fn new(accounts: Vec<Account>) -> Self {
+        let mut cache_key = [0; 32];
+        OsRng.fill_bytes(&mut cache_key);
+        Accounts {
+            accounts,
+            verified: Mutex::new(HashMap::new()),
+            cache_key,
+        }
+    }
+
+    fn cache_entry(&self, user: &str, password: &str) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(self.cache_key)
+            .chain_update(user)
+            .chain_update([0])
+            .chain_update(password)
+            .finalize()
+            .into()
+    }
+
+    /// The account `user` logs in to, if `password` is right.
+    fn verify(&self, user: &str, password: &str) -> Option<&Account> {
+        let account = self.accounts.iter().find(|a| a.user == user);
+        let entry = self.cache_entry(user, password);
+        let now = Instant::now();
+
+        if let Some(account) = account {
+            let verified = self.verified.lock().unwrap().get(&entry).copied();
+            if verified.is_some_and(|at| now.duration_since(at) < VERIFIED_CACHE_TTL) {
+                return Some(account);
+            }
+        }
+
+        let hash = account.map_or(dummy_hash(), |a| &a.password_hash);
+        if !verify_password(password, hash) {
+            return None;
+        }
+        let account = account?;
+
+        let mut verified = self.verified.lock().unwrap();
+        verified.retain(|_, at| now.duration_since(*at) < VERIFIED_CACHE_TTL);
+        verified.insert(entry, now);
+        Some(account)
+    }
+}
+
+/// HTTP Basic authentication against the configured accounts.
+#[derive(Debug)]
+pub(crate) struct BasicAuthenticator {
+    accounts: Arc<Accounts>,
+    challenge: HeaderValue,
+}
+
+impl BasicAuthenticator {
+    pub(crate) fn new(accounts: Vec<Account>, realm: &str) -> Self {
+        let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm);
+        BasicAuthenticator {
+            accounts: Arc::new(Accounts::new(accounts)),
+            challenge: HeaderValue::try_from(challenge).expect("realm is checked by validate"),
+        }
+    }
+}
+
+impl Authenticator for BasicAuthenticator {
+    fn authenticate<'a>(&'a self, parts: &'a Parts) -> BoxFuture<'a, Result<Identity, Challenge>> {
+        let auth = parts.headers.typed_get::<Authorization<Basic>>();
+        let accounts = self.accounts.clone();
+        Box::pin(async move {
+            let identity = match auth {
+                // hashing takes a while, keep it off the async workers
+                Some(auth) => tokio::task::spawn_blocking(move || {
+                    accounts
+                        .verify(auth.username(), auth.password())
+                        .map(Identity::from)
+                })
+                .await
+                .unwrap_or(None),
+                None => None,
+            };
+            identity.ok_or_else(|| Challenge(vec![self.challenge.clone()]))
+        })
+    }
+}
+
+/// State of the authentication middleware.
+pub(crate) struct Authentication {
+    /// Requests without a share token pass unauthenticated if unset.
+    pub(crate) authenticator: Option<Arc<dyn Authenticator>>,
+    pub(crate) shares: Option<ShareAuth>,
+    /// Accounts that log in with a client certificate.
+    pub(crate) client_cert_accounts: Vec<Account>,
+    /// Let requests without credentials read.
+    pub(crate) anonymous_read: bool,
+    pub(crate) lockouts: Arc<Lockouts>,
+    pub(crate) lockout: Option<LockoutPolicy>,
+}
+
+/// Added to the responses to requests whose credentials or share token were
+/// rejected, for the metrics and events outside the authentication.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AuthFailed;
+
+/// Run `req` as `identity`. The identity is added to the response as well,
+/// for the layers outside the authentication.
+async fn run_as<B>(mut req: Request<B>, identity: Identity, next: Next<B>) -> Response {
+    req.extensions_mut().insert(identity.clone());
+    let mut resp = next.run(req).await;
+    resp.extensions_mut().insert(identity);
+    resp
+}
+
+pub(crate) async fn authenticate_middleware<B>(
+    State(auth): State<Arc<Authentication>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| BanTarget::Ip(addr.ip()));
+    let user = request_user(req.headers()).map(BanTarget::User);
+    let targets = client.into_iter().chain(user).collect::<Vec<_>>();
+
+    if auth.lockout.is_some() {
+        if let Some(wait) = auth.lockouts.blocked(&targets) {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, (wait.as_secs() + 1).to_string())],
+                "Too Many Requests",
+            )
+                .into_response();
+        }
+    }
+
+    let (parts, body) = req.into_parts();
+    if let Some((shares, (token, prefix))) = auth
+        .shares
+        .as_ref()
+        .and_then(|shares| Some((shares, shares.token(&parts)?)))
+    {
+        let Some(ShareGrant {
+            identity,
+            expires,
+            counted,
+        }) = shares.authorize(token, &parts.method)
+        else {
+            if let Some(policy) = &auth.lockout {
+                auth.lockouts.failed(&targets, policy);
+            }
+            return (StatusCode::FORBIDDEN, Extension(AuthFailed), "Forbidden").into_response();
+        };
+        let mut req = Request::from_parts(parts, body);
+        if let Some(prefix) = prefix {
+            req.extensions_mut().insert(SharePrefix { prefix, expires });
+        }
+        let resp = run_as(req, identity, next).await;
+        // only transfers that went through use up the share
+        if let Some(id) = counted.filter(|_| !resp.status().is_success()) {
+            shares.uses.give_back(&id);
+        }
+        return resp;
+    }
+
+    if let Some(account) = parts.extensions.get::<ClientCert>().and_then(|cert| {
+        auth.client_cert_accounts.iter().find(|account| {
+            account
+                .client_cert
+                .as_ref()
+                .is_some_and(|name| cert.names().contains(name))
+        })
+    }) {
+        let identity = Identity::from(account);
+        return run_as(Request::from_parts(parts, body), identity, next).await;
+    }
+
+    // clients that want to write get the challenge and retry with credentials
+    if auth.anonymous_read
+        && !parts.headers.contains_key(header::AUTHORIZATION)
+        && Permission::ReadOnly.allows(&parts.method)
+    {
+        let identity = Identity {
+            user: ANONYMOUS.to_string(),
+            root: None,
+            permission: Permission::ReadOnly,
+        };
+        return run_as(Request::from_parts(parts, body), identity, next).await;
+    }
+
+    let Some(authenticator) = &auth.authenticator else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+    match authenticator.authenticate(&parts).await {
+        Ok(identity) => {
+            auth.lockouts.succeeded(&targets);
+            run_as(Request::from_parts(parts, body), identity, next).await
+        }
+        Err(Challenge(challenges)) => {
+            // a request without credentials is just asking for the challenge
+            let credentials = parts.headers.contains_key(header::AUTHORIZATION);
+            if let Some(policy) = &auth.lockout {
+                if credentials {
+                    auth.lockouts.failed(&targets, policy);
+                }
+            }
+            (
+                StatusCode::UNAUTHORIZED,
+                credentials.then_some(Extension(AuthFailed)),
+                AppendHeaders(
+                    challenges
+                        .into_iter()
+                        .map(|challenge| (header::WWW_AUTHENTICATE, challenge)),
+                ),
+                "Unauthorized",
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{io, path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum_server::{
+    accept::{Accept, DefaultAcceptor},
+    tls_rustls::RustlsAcceptor,
+};
+use futures_util::future::BoxFuture;
+use rustls::{
+    server::ClientCertVerifier,
+    server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient},
+    RootCertStore,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use x509_parser::{extensions::GeneralName, prelude::*};
+
+use crate::connection::ConnectionService;
+
+/// CA bundle that client certificates are verified against, see
+/// [`TlsConfig::client_ca_pem`](crate::TlsConfig::client_ca_pem).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ClientCa {
+    Pem(Vec<u8>),
+    PemFile(PathBuf),
+}
+
+impl ClientCa {
+    /// Verifier for the handshake. With `required` clients without a
+    /// certificate are refused, otherwise they are left to the other
+    /// authentication.
+    pub(crate) async fn verifier(&self, required: bool) -> Result<Arc<dyn ClientCertVerifier>> {
+        let pem = match self {
+            ClientCa::Pem(pem) => pem.clone(),
+            ClientCa::PemFile(path) => tokio::fs::read(path)
+                .await
+                .with_context(|| format!("failed to read {}", path.display()))?,
+        };
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &*pem).context("failed to parse client ca")? {
+            roots
+                .add(&rustls::Certificate(cert))
+                .context("failed to load client ca")?;
+        }
+        if roots.is_empty() {
+            return Err(anyhow::anyhow!("client ca contains no certificate"));
+        }
+
+        Ok(if required {
+            AllowAnyAuthenticatedClient::new(roots).boxed()
+        } else {
+            AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+        })
+    }
+}
+
+/// The names of the verified certificate a client presented during the TLS
+/// handshake. Requests of such connections carry it as extension, so a
+/// custom [`Authenticator`](crate::Authenticator) can use it as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCert {
+    names: Vec<String>,
+}
+
+impl ClientCert {
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let common_names = cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok())
+            .map(str::to_string);
+        let alt_names = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .into_iter()
+            .flat_map(|san| &san.value.general_names)
+            .filter_map(|name| match name {
+                GeneralName::DNSName(name) | GeneralName::RFC822Name(name) => {
+                    Some(name.to_string())
+                }
+                _ => None,
+            });
+        Some(ClientCert {
+            names: common_names.chain(alt_names).collect(),
+        })
+    }
+
+    /// The common names of the subject followed by the DNS and email
+    /// subject alternative names.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// TLS acceptor that hands the client certificate of a connection to its
+/// [`ConnectionService`].
+#[derive(Debug, Clone)]
+pub(crate) struct ClientCertAcceptor(pub(crate) RustlsAcceptor);
+
+impl<I, S> Accept<I, ConnectionService<S>> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = ConnectionService<S>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: ConnectionService<S>) -> Self::Future {
+        let accept =
+            <RustlsAcceptor<DefaultAcceptor> as Accept<_, _>>::accept(&self.0, stream, service);
+        Box::pin(async move {
+            let (stream, mut service) = accept.await?;
+            // rustls only hands out certificates that passed verification
+            service.client_cert = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| ClientCert::from_der(&cert.0))
+                .map(Arc::new);
+            Ok((stream, service))
+        })
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    extract::{connect_info::Connected, ConnectInfo},
+    http::Request,
+};
+use tokio::sync::broadcast;
+use tower::Service;
+
+use crate::{client_cert::ClientCert, events::DavEvent, metrics::Metrics};
+
+/// Make service for the listeners. Every connection is counted as active in
+/// the [`Metrics`] until it is closed, is published as
+/// [`DavEvent::Connected`], and its requests carry the peer address as
+/// [`ConnectInfo`] and the verified client certificate, if any, as
+/// [`ClientCert`].
+#[derive(Debug, Clone)]
+pub(crate) struct MakeConnectionService<S> {
+    pub(crate) inner: S,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) events: broadcast::Sender<DavEvent>,
+}
+
+impl<S: Clone, T> Service<T> for MakeConnectionService<S>
+where
+    SocketAddr: Connected<T>,
+{
+    type Response = ConnectionService<S>;
+    type Error = std::convert::Infallible;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let remote_addr = SocketAddr::connect_info(target);
+        self.metrics.connection_opened();
+        let _ = self.events.send(DavEvent::Connected {
+            client: remote_addr,
+        });
+        std::future::ready(Ok(ConnectionService {
+            inner: self.inner.clone(),
+            remote_addr,
+            client_cert: None,
+            _guard: Arc::new(ConnectionGuard(self.metrics.clone())),
+        }))
+    }
+}
+
+/// The per connection service; it is dropped when the connection closes.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionService<S> {
+    inner: S,
+    remote_addr: SocketAddr,
+    /// Set by [`ClientCertAcceptor`](crate::client_cert::ClientCertAcceptor)
+    /// after the handshake.
+    pub(crate) client_cert: Option<Arc<ClientCert>>,
+    _guard: Arc<ConnectionGuard>,
+}
+
+impl<S: Service<Request<B>>, B> Service<Request<B>> for ConnectionService<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(ConnectInfo(self.remote_addr));
+        if let Some(cert) = &self.client_cert {
+            req.extensions_mut().insert(ClientCert::clone(cert));
+        }
+        self.inner.call(req)
+    }
+}
+
+#[derive(Debug)]
+struct ConnectionGuard(Arc<Metrics>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connection_closed();
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue};
+use futures_util::future::BoxFuture;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::auth::{Account, Authenticator, Challenge, Identity};
+
+/// How long a nonce handed out in a challenge may be used. Clients get a
+/// `stale` challenge afterwards and retry without asking for the password.
+const NONCE_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Hash algorithms of RFC 7616 Digest authentication. `-sess` variants are
+/// not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    /// Obsolete, but the only one many clients such as davfs2 know.
+    Md5,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    const ALL: [DigestAlgorithm; 2] = [DigestAlgorithm::Sha256, DigestAlgorithm::Md5];
+
+    fn name(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    fn hash(self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 => hex(&Md5::digest(data)),
+            DigestAlgorithm::Sha256 => hex(&Sha256::digest(data)),
+        }
+    }
+
+    /// `H(user:realm:password)`, all the server needs to know to check the
+    /// responses of `user`.
+    pub fn ha1(self, user: &str, realm: &str, password: &str) -> String {
+        self.hash(&format!("{}:{}:{}", user, realm, password))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// The secrets behind the nonces of a server. They outlive the
+/// [`DigestAuthenticator`]s of its routers, so clients keep their nonces
+/// when the router is rebuilt on a reload.
+#[derive(Debug)]
+pub(crate) struct DigestKeys {
+    /// Signs the nonces so they need not be stored until they are used.
+    key: [u8; 32],
+    opaque: String,
+    /// Nonce counts seen per nonce, to reject replayed requests.
+    used: Mutex<HashMap<String, (u64, HashSet<String>)>>,
+}
+
+impl Default for DigestKeys {
+    fn default() -> Self {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+        let mut opaque = [0; 16];
+        OsRng.fill_bytes(&mut opaque);
+        DigestKeys {
+            key,
+            opaque: hex(&opaque),
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// HTTP Digest authentication against the configured accounts.
+#[derive(Debug)]
+pub(crate) struct DigestAuthenticator {
+    accounts: Vec<Account>,
+    realm: String,
+    keys: Arc<DigestKeys>,
+}
+
+impl DigestAuthenticator {
+    pub(crate) fn new(accounts: Vec<Account>, realm: String, keys: Arc<DigestKeys>) -> Self {
+        DigestAuthenticator {
+            accounts,
+            realm,
+            keys,
+        }
+    }
+
+    fn nonce_mac(&self, issued: u64) -> String {
+        hex(&Sha256::new()
+            .chain_update(self.keys.key)
+            .chain_update(issued.to_be_bytes())
+            .finalize()[..16])
+    }
+
+    fn nonce(&self, now: u64) -> String {
+        format!("{:016x}{}", now, self.nonce_mac(now))
+    }
+
+    /// When `nonce` was issued, if it was issued by this server.
+    fn nonce_issued(&self, nonce: &str) -> Option<u64> {
+        let issued = u64::from_str_radix(nonce.get(..16)?, 16).ok()?;
+        constant_time_eq(&nonce.as_bytes()[16..], self.nonce_mac(issued).as_bytes())
+            .then_some(issued)
+    }
+
+    /// Check the nonce and nonce count of a request whose response is right.
+    /// `false` means the client should retry with a fresh nonce.
+    fn use_nonce(&self, nonce: &str, nc: &str, now: u64) -> bool {
+        let Some(issued) = self.nonce_issued(nonce) else {
+            return false;
+        };
+        if now.saturating_sub(issued) > NONCE_LIFETIME.as_secs() {
+            return false;
+        }
+
+        let mut used = self.keys.used.lock().unwrap();
+        used.retain(|_, (issued, _)| now.saturating_sub(*issued) <= NONCE_LIFETIME.as_secs());
+        used.entry(nonce.to_string())
+            .or_insert_with(|| (issued, HashSet::new()))
+            .1
+            .insert(nc.to_string())
+    }
+
+    fn challenge(&self, stale: bool) -> Challenge {
+        let nonce = self.nonce(unix_now());
+        let challenges = DigestAlgorithm::ALL.map(|algorithm| {
+            let challenge = format!(
+                "Digest realm=\"{}\", qop=\"auth\", algorithm={}, nonce=\"{}\", opaque=\"{}\", stale={}",
+                self.realm,
+                algorithm.name(),
+                nonce,
+                self.keys.opaque,
+                stale
+            );
+            HeaderValue::try_from(challenge).expect("realm is checked by validate")
+        });
+        Challenge(challenges.into())
+    }
+
+    /// The account a request is authorized as, or whether the nonce of a
+    /// rejected request was merely stale.
+    fn authorize(&self, req: &Parts) -> Result<&Account, bool> {
+        let Some(params) = req
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|auth| auth.to_str().ok())
+            .and_then(digest_params)
+        else {
+            return Err(false);
+        };
+        let param = |name: &str| params.get(name).map(String::as_str);
+
+        let algorithm = match param("algorithm").unwrap_or("MD5") {
+            a if a.eq_ignore_ascii_case("MD5") => DigestAlgorithm::Md5,
+            a if a.eq_ignore_ascii_case("SHA-256") => DigestAlgorithm::Sha256,
+            _ => return Err(false),
+        };
+        let (
+            Some(user),
+            Some(realm),
+            Some(nonce),
+            Some(uri),
+            Some(response),
+            Some("auth"),
+            Some(nc),
+            Some(cnonce),
+        ) = (
+            param("username"),
+            param("realm"),
+            param("nonce"),
+            param("uri"),
+            param("response"),
+            param("qop"),
+            param("nc"),
+            param("cnonce"),
+        )
+        else {
+            return Err(false);
+        };
+        if realm != self.realm
+            || param("opaque").is_some_and(|opaque| opaque != self.keys.opaque)
+            || !same_uri(uri, req)
+        {
+            return Err(false);
+        }
+
+        let Some((account, ha1)) = self
+            .accounts
+            .iter()
+            .find(|a| a.user == user)
+            .and_then(|a| Some((a, a.digest_ha1.get(&algorithm)?)))
+        else {
+            return Err(false);
+        };
+        let ha2 = algorithm.hash(&format!("{}:{}", req.method, uri));
+        let expected = algorithm.hash(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+        if !constant_time_eq(
+            expected.as_bytes(),
+            response.to_ascii_lowercase().as_bytes(),
+        ) {
+            return Err(false);
+        }
+
+        // the client knows the password, a bad nonce only needs a retry
+        if !self.use_nonce(nonce, nc, unix_now()) {
+            return Err(true);
+        }
+        Ok(account)
+    }
+}
+
+impl Authenticator for DigestAuthenticator {
+    fn authenticate<'a>(&'a self, parts: &'a Parts) -> BoxFuture<'a, Result<Identity, Challenge>> {
+        let result = self
+            .authorize(parts)
+            .map(Identity::from)
+            .map_err(|stale| self.challenge(stale));
+        Box::pin(async move { result })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether the `uri` parameter names the requested resource, either as
+/// origin form or as absolute URI.
+fn same_uri(uri: &str, req: &Parts) -> bool {
+    let requested = req.uri.path_and_query().map(|p| p.as_str());
+    uri.parse::<axum::http::Uri>()
+        .ok()
+        .and_then(|uri| uri.path_and_query().cloned())
+        .is_some_and(|uri| Some(uri.as_str()) == requested)
+}
+
+/// The parameters of a `Digest` authorization header.
+fn digest_params(header: &str) -> Option<HashMap<String, String>> {
+    let (scheme, mut rest) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Digest") {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        if rest.is_empty() {
+            return Some(params);
+        }
+        let (name, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let parsed = if let Some(quoted) = value.strip_prefix('"') {
+            let mut parsed = String::new();
+            let mut chars = quoted.char_indices();
+            loop {
+                match chars.next()? {
+                    (_, '\\') => parsed.push(chars.next()?.1),
+                    (i, '"') => {
+                        rest = &quoted[i + 1..];
+                        break;
+                    }
+                    (_, c) => parsed.push(c),
+                }
+            }
+            parsed
+        } else {
+            let end = value.find(',').unwrap_or(value.len());
+            rest = &value[end..];
+            value[..end].trim_end().to_string()
+        };
+        params.insert(name.trim().to_ascii_lowercase(), parsed);
+    }
+}
+
+/// The user name of a `Digest` authorization header.
+pub(crate) fn digest_user(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(digest_params)
+        .and_then(|mut params| params.remove("username"))
+}
@@ -0,0 +1,131 @@
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{Request, Uri},
+    middleware::Next,
+    response::Response,
+};
+use tokio::sync::broadcast;
+
+use crate::{
+    access_log::request_user,
+    auth::{AuthFailed, Identity},
+    share::redact_token,
+};
+
+/// Number of events a slow subscriber may fall behind before it starts
+/// missing events.
+pub(crate) const EVENT_CAPACITY: usize = 256;
+
+/// Something that happened on the share, published by
+/// [`DavServer::subscribe`](crate::DavServer::subscribe). Paths are the
+/// request paths as sent by the client, including the `http_path` prefix,
+/// with share tokens redacted.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DavEvent {
+    Connected {
+        client: SocketAddr,
+    },
+    AuthFailed {
+        client: Option<SocketAddr>,
+        user: Option<String>,
+    },
+    Uploaded {
+        user: Option<String>,
+        path: String,
+    },
+    Deleted {
+        user: Option<String>,
+        path: String,
+    },
+    Moved {
+        user: Option<String>,
+        from: String,
+        to: String,
+    },
+    Copied {
+        user: Option<String>,
+        from: String,
+        to: String,
+    },
+    CollectionCreated {
+        user: Option<String>,
+        path: String,
+    },
+    Locked {
+        user: Option<String>,
+        path: String,
+    },
+    Unlocked {
+        user: Option<String>,
+        path: String,
+    },
+}
+
+/// Publishes a [`DavEvent`] for every successful modifying request, by the
+/// [`Identity`] it ran as, and every request whose credentials or share
+/// token were rejected.
+pub(crate) async fn events_middleware(
+    State(events): State<broadcast::Sender<DavEvent>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    // the name the client claims, only told when its login fails
+    let claimed_user = request_user(req.headers());
+    let method = req.method().clone();
+    let path = redact_token(req.uri().path()).into_owned();
+    let destination = req
+        .headers()
+        .get("Destination")
+        .and_then(|d| d.to_str().ok())
+        .and_then(|d| d.parse::<Uri>().ok())
+        .map(|d| redact_token(d.path()).into_owned());
+
+    let resp = next.run(req).await;
+    let status = resp.status();
+    let user = resp
+        .extensions()
+        .get::<Identity>()
+        .map(|identity| identity.user.clone());
+
+    let event = if resp.extensions().get::<AuthFailed>().is_some() {
+        Some(DavEvent::AuthFailed {
+            client,
+            user: claimed_user,
+        })
+    } else if !status.is_success() {
+        None
+    } else {
+        match (method.as_str(), destination) {
+            ("PUT", _) => Some(DavEvent::Uploaded { user, path }),
+            ("DELETE", _) => Some(DavEvent::Deleted { user, path }),
+            ("MOVE", Some(to)) => Some(DavEvent::Moved {
+                user,
+                from: path,
+                to,
+            }),
+            ("COPY", Some(to)) => Some(DavEvent::Copied {
+                user,
+                from: path,
+                to,
+            }),
+            ("MKCOL", _) => Some(DavEvent::CollectionCreated { user, path }),
+            ("LOCK", _) => Some(DavEvent::Locked { user, path }),
+            ("UNLOCK", _) => Some(DavEvent::Unlocked { user, path }),
+            _ => None,
+        }
+    };
+    if let Some(event) = event {
+        // no subscribers is not an error
+        let _ = events.send(event);
+    }
+
+    resp
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+
+use crate::{auth::Account, digest::constant_time_eq};
+
+const APR1_MAGIC: &str = "$apr1$";
+
+/// Alphabet of the crypt(3) flavoured base64 APR1 hashes are written in.
+const CRYPT_BASE64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The accounts of an Apache htpasswd file. Entries may be hashed with
+/// bcrypt, SHA1 (`{SHA}`) or APR1-MD5 (`$apr1$`); crypt(3) and plain text
+/// entries are refused.
+pub(crate) fn load(path: &Path) -> Result<Vec<Account>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            let (user, hash) = line
+                .split_once(':')
+                .with_context(|| format!("{}:{}: missing ':'", path.display(), i + 1))?;
+            Account::from_hash(user.to_string(), hash.to_string())
+                .with_context(|| format!("{}:{}", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Whether `hash` is a SHA1 or APR1-MD5 hash as written by `htpasswd`.
+pub(crate) fn is_htpasswd_hash(hash: &str) -> bool {
+    hash.starts_with("{SHA}") || hash.starts_with(APR1_MAGIC)
+}
+
+/// Whether `hash` is well formed, see [`is_htpasswd_hash`].
+pub(crate) fn is_valid_hash(hash: &str) -> bool {
+    if let Some(sha) = hash.strip_prefix("{SHA}") {
+        return STANDARD.decode(sha).is_ok_and(|sha| sha.len() == 20);
+    }
+    hash.strip_prefix(APR1_MAGIC)
+        .and_then(|rest| rest.split_once('$'))
+        .is_some_and(|(salt, hash)| {
+            salt.len() <= 8 && hash.len() == 22 && hash.bytes().all(|b| CRYPT_BASE64.contains(&b))
+        })
+}
+
+/// Check `password` against a SHA1 or APR1-MD5 hash.
+pub(crate) fn verify(password: &str, hash: &str) -> bool {
+    if let Some(sha) = hash.strip_prefix("{SHA}") {
+        return STANDARD
+            .decode(sha)
+            .is_ok_and(|sha| constant_time_eq(&Sha1::digest(password), &sha));
+    }
+    match hash
+        .strip_prefix(APR1_MAGIC)
+        .and_then(|rest| rest.split_once('$'))
+    {
+        Some((salt, _)) => constant_time_eq(apr1(password, salt).as_bytes(), hash.as_bytes()),
+        None => false,
+    }
+}
+
+/// The APR1 variant of md5crypt, as implemented by Apache's `htpasswd -m`.
+fn apr1(password: &str, salt: &str) -> String {
+    let password = password.as_bytes();
+    let salt = &salt.as_bytes()[..salt.len().min(8)];
+
+    let alternate = Md5::new()
+        .chain_update(password)
+        .chain_update(salt)
+        .chain_update(password)
+        .finalize();
+    let mut md5 = Md5::new()
+        .chain_update(password)
+        .chain_update(APR1_MAGIC)
+        .chain_update(salt);
+    for chunk in (0..password.len()).step_by(16) {
+        md5.update(&alternate[..(password.len() - chunk).min(16)]);
+    }
+    let mut bits = password.len();
+    while bits > 0 {
+        if bits & 1 == 1 {
+            md5.update([0]);
+        } else {
+            md5.update(&password[..1]);
+        }
+        bits >>= 1;
+    }
+    let mut hash = md5.finalize();
+
+    // deliberately slow
+    for round in 0..1000 {
+        let mut md5 = Md5::new();
+        if round & 1 == 1 {
+            md5.update(password);
+        } else {
+            md5.update(hash);
+        }
+        if round % 3 != 0 {
+            md5.update(salt);
+        }
+        if round % 7 != 0 {
+            md5.update(password);
+        }
+        if round & 1 == 1 {
+            md5.update(hash);
+        } else {
+            md5.update(password);
+        }
+        hash = md5.finalize();
+    }
+
+    let mut encoded = String::with_capacity(22);
+    let mut push = |mut value: u32, chars: usize| {
+        for _ in 0..chars {
+            encoded.push(CRYPT_BASE64[(value & 0x3f) as usize] as char);
+            value >>= 6;
+        }
+    };
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+        push(
+            (hash[a] as u32) << 16 | (hash[b] as u32) << 8 | hash[c] as u32,
+            4,
+        );
+    }
+    push(hash[11] as u32, 2);
+
+    format!(
+        "{}{}${}",
+        APR1_MAGIC,
+        std::str::from_utf8(salt).unwrap_or_default(),
+        encoded
+    )
+}
@@ -0,0 +1,180 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+use arc_swap::ArcSwap;
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::DavConfig;
+
+/// A range of addresses in CIDR notation, such as `192.168.0.0/16` or
+/// `fe80::/10`. A plain address stands for itself alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// The range of addresses sharing the first `prefix` bits with `addr`.
+    /// Errors if `prefix` is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> anyhow::Result<Self> {
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return Err(anyhow::anyhow!(
+                "prefix /{} is too long for {}",
+                prefix,
+                addr
+            ));
+        }
+        // clients are compared as IPv4 if they can be, so must be the range
+        let (addr, prefix) = match addr {
+            IpAddr::V6(v6) if prefix >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => (IpAddr::V4(v4), prefix - 96),
+                None => (addr, prefix),
+            },
+            _ => (addr, prefix),
+        };
+        Ok(IpNet {
+            addr: mask(addr, prefix),
+            prefix,
+        })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = canonical(addr);
+        addr.is_ipv4() == self.addr.is_ipv4() && mask(addr, self.prefix) == self.addr
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| anyhow::anyhow!("invalid address in {}", s))?;
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .map_err(|_| anyhow::anyhow!("invalid prefix in {}", s))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        IpNet::new(addr, prefix)
+    }
+}
+
+impl std::fmt::Display for IpNet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// IPv4 clients of a listener bound to an IPv6 address show up as mapped
+/// addresses, compare them as IPv4.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+/// Which client addresses may connect, see [`DavConfig::ip_filter`](crate::DavConfig::ip_filter).
+/// An address in a `deny` range is refused; otherwise it is accepted if
+/// `allow` is empty or one of its ranges contains it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IpFilter {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    /// Loopback, private, link local and unique local addresses only.
+    pub fn private_lan() -> Self {
+        let allow = [
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "169.254.0.0/16",
+            "::1/128",
+            "fc00::/7",
+            "fe80::/10",
+        ];
+        IpFilter {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+            deny: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        !self.deny.iter().any(|net| net.contains(addr))
+            && (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(addr)))
+    }
+}
+
+/// Refuses clients the filter does not allow, before they get to log in.
+/// Requests without a known peer address are refused as well.
+pub(crate) async fn ip_filter_middleware<B>(
+    State(filter): State<Arc<IpFilter>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    filter_client(&filter, req, next).await
+}
+
+/// [`ip_filter_middleware`] with the filter of the current config, for the
+/// listeners whose router is not rebuilt on a reload.
+pub(crate) async fn config_ip_filter_middleware<B>(
+    State(config): State<Arc<ArcSwap<DavConfig>>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    filter_client(&config.load_full().ip_filter, req, next).await
+}
+
+async fn filter_client<B>(filter: &IpFilter, req: Request<B>, next: Next<B>) -> Response {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    match client {
+        Some(ip) if filter.allows(ip) => next.run(req).await,
+        _ => {
+            tracing::debug!(?client, "client refused by ip filter");
+            (StatusCode::FORBIDDEN, "Forbidden").into_response()
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A JSON file the server keeps state in across restarts. Saves are written
+/// by a blocking thread to a temporary file that then replaces the old one,
+/// so requests never wait for the disk and a crash never leaves half a file.
+#[derive(Debug, Default)]
+pub(crate) struct JsonFile {
+    path: Mutex<Option<PathBuf>>,
+    writer: Arc<Writer>,
+}
+
+#[derive(Debug, Default)]
+struct Writer {
+    pending: Mutex<Pending>,
+    /// Signalled when the writer has nothing left to write.
+    idle: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    /// The newest content not written yet. Older content is never written
+    /// once newer content is saved.
+    content: Option<(PathBuf, Vec<u8>)>,
+    writing: bool,
+}
+
+impl JsonFile {
+    /// The content of the file at `path`, `None` if there is none.
+    pub(crate) fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Save to `path` from now on, or nowhere.
+    pub(crate) fn set_path(&self, path: Option<&Path>) {
+        *self.path.lock().unwrap() = path.map(Path::to_path_buf);
+    }
+
+    /// Write `value` to the file in the background. Callers save while
+    /// holding the lock of the state, so the last save is the last written.
+    pub(crate) fn save(&self, value: &impl Serialize) {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            return;
+        };
+        let content = match serde_json::to_vec(value) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!(file = %path.display(), "failed to save: {}", e);
+                return;
+            }
+        };
+
+        let mut pending = self.writer.pending.lock().unwrap();
+        pending.content = Some((path, content));
+        if pending.writing {
+            return;
+        }
+        pending.writing = true;
+        drop(pending);
+        let writer = self.writer.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || writer.run());
+            }
+            Err(_) => writer.run(),
+        }
+    }
+
+    /// Block until everything saved so far is written.
+    pub(crate) fn flush(&self) {
+        let mut pending = self.writer.pending.lock().unwrap();
+        while pending.writing {
+            pending = self.writer.idle.wait(pending).unwrap();
+        }
+    }
+}
+
+impl Writer {
+    /// Write the pending content until there is none left.
+    fn run(&self) {
+        loop {
+            let mut pending = self.pending.lock().unwrap();
+            let Some((path, content)) = pending.content.take() else {
+                pending.writing = false;
+                self.idle.notify_all();
+                return;
+            };
+            drop(pending);
+            if let Err(e) = replace(&path, &content) {
+                tracing::warn!(file = %path.display(), "failed to save: {}", e);
+            }
+        }
+    }
+}
+
+/// Replace the file at `path` with `content` in one step.
+fn replace(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)
+}
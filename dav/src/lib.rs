@@ -1,48 +1,179 @@
-use std::net::{Ipv6Addr, SocketAddr};
+use std::{
+    collections::HashMap,
+    net::{Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
+use access_log::access_log_middleware;
 use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use auth::{authenticate_middleware, Authentication, BasicAuthenticator};
+pub use auth::{
+    hash_password, Account, AuthScheme, Authenticator, Challenge, Identity, Permission, ANONYMOUS,
+};
 use axum::{
-    extract::{State, TypedHeader},
-    headers::Authorization,
-    http::{Request, StatusCode},
-    middleware::Next,
+    body::Body,
+    http::{header, uri::Authority, HeaderValue, Method, Request, StatusCode},
     response::{IntoResponse, Response},
+    routing::get,
 };
 use axum_server::tls_rustls::RustlsConfig;
-use dav_server::{fakels::FakeLs, localfs::LocalFs};
-use tower::service_fn;
+pub use client_cert::ClientCert;
+use client_cert::{ClientCa, ClientCertAcceptor};
+use connection::MakeConnectionService;
+use dav_server::{davpath::DavPath, DavHandler, DavMethodSet};
+use digest::{DigestAuthenticator, DigestKeys};
+use events::{events_middleware, DavEvent, EVENT_CAPACITY};
+use ip_filter::{config_ip_filter_middleware, ip_filter_middleware, IpFilter, IpNet};
+use lockout::{Ban, BanTarget, LockoutPolicy, Lockouts};
+use locks::Locks;
+use metrics::{metrics_middleware, Metrics};
+use mount::mount_list_router;
+pub use mount::{Mount, MountAccess};
+use props::{PropFs, Properties};
+pub use share::{Share, ShareKey, ShareScope};
+use share::{ShareAuth, SharePrefix, ShareUses, SHARE_SEGMENT};
+use tokio::sync::broadcast;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tower::{service_fn, ServiceExt};
 use utils::WithProcedure;
 
+mod access_log;
+pub mod auth;
+mod client_cert;
+mod connection;
+pub mod digest;
+pub mod events;
+mod htpasswd;
+pub mod ip_filter;
+mod json_file;
+pub mod lockout;
+mod locks;
+pub mod metrics;
+pub mod mount;
+mod props;
+pub mod share;
 pub mod utils;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct DavConfig {
-    bind: SocketAddr,
+    listeners: Vec<Listener>,
     root: Option<String>,
     http_path: Option<String>,
-    user: Option<String>,
-    password: Option<String>,
+    mounts: Vec<Mount>,
+    accounts: Vec<Account>,
+    htpasswd: Option<PathBuf>,
+    htpasswd_poll_interval: Duration,
+    auth_scheme: AuthScheme,
+    realm: String,
+    lockout: Option<LockoutPolicy>,
+    anonymous_read: bool,
+    read_only: bool,
+    ip_filter: IpFilter,
+    share_key: Option<ShareKey>,
+    revoked_shares: Vec<String>,
+    share_use_file: Option<PathBuf>,
+    lock_file: Option<PathBuf>,
+    property_file: Option<PathBuf>,
     tls_config: Option<TlsConfig>,
     enable_tls: bool,
+    https_redirect: Vec<SocketAddr>,
+    metrics: Option<MetricsEndpoint>,
+    shutdown_grace_period: Duration,
+}
+
+impl std::fmt::Debug for DavConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DavConfig")
+            .field("listeners", &self.listeners)
+            .field("root", &self.root)
+            .field("http_path", &self.http_path)
+            .field("mounts", &self.mounts)
+            .field("accounts", &self.accounts)
+            .field("htpasswd", &self.htpasswd)
+            .field("htpasswd_poll_interval", &self.htpasswd_poll_interval)
+            .field("auth_scheme", &self.auth_scheme)
+            .field("realm", &self.realm)
+            .field("lockout", &self.lockout)
+            .field("anonymous_read", &self.anonymous_read)
+            .field("read_only", &self.read_only)
+            .field("ip_filter", &self.ip_filter)
+            .field("share_key", &self.share_key)
+            .field("revoked_shares", &self.revoked_shares)
+            .field("share_use_file", &self.share_use_file)
+            .field("lock_file", &self.lock_file)
+            .field("property_file", &self.property_file)
+            .field("tls_config", &self.tls_config)
+            .field("enable_tls", &self.enable_tls)
+            .field("https_redirect", &self.https_redirect)
+            .field("metrics", &self.metrics)
+            .field("shutdown_grace_period", &self.shutdown_grace_period)
+            .finish()
+    }
+}
+
+/// Printed in place of passwords and private keys in `Debug` output.
+const REDACTED: &str = "<redacted>";
+
+/// Where the Prometheus metrics are served.
+#[derive(Debug, Clone, PartialEq)]
+enum MetricsEndpoint {
+    /// On the dav listeners, behind the same authorization.
+    Path(String),
+    /// On a separate plain HTTP listener, at any path.
+    Listener(SocketAddr),
 }
 
 impl Default for DavConfig {
     fn default() -> Self {
         DavConfig {
-            bind: SocketAddr::from((Ipv6Addr::UNSPECIFIED, 8080)),
+            listeners: vec![Listener::new(SocketAddr::from((
+                Ipv6Addr::UNSPECIFIED,
+                8080,
+            )))],
             root: None,
             http_path: None,
-            user: None,
-            password: None,
+            mounts: Vec::new(),
+            accounts: Vec::new(),
+            htpasswd: None,
+            htpasswd_poll_interval: Duration::from_secs(2),
+            auth_scheme: AuthScheme::default(),
+            realm: "dav".to_string(),
+            lockout: Some(LockoutPolicy::default()),
+            anonymous_read: false,
+            read_only: false,
+            ip_filter: IpFilter::default(),
+            share_key: None,
+            revoked_shares: Vec::new(),
+            share_use_file: None,
+            lock_file: None,
+            property_file: None,
             tls_config: None,
             enable_tls: false,
+            https_redirect: Vec::new(),
+            metrics: None,
+            shutdown_grace_period: Duration::from_secs(10),
         }
     }
 }
 
 impl DavConfig {
+    /// Listen on `bind` only, replacing any configured listener.
     pub fn bind(mut self, bind: SocketAddr) -> Self {
-        self.bind = bind;
+        self.listeners = vec![Listener::new(bind)];
+        self
+    }
+
+    /// Replace all configured listeners.
+    pub fn listeners(mut self, listeners: Vec<Listener>) -> Self {
+        self.listeners = listeners;
+        self
+    }
+
+    pub fn add_listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
         self
     }
 
@@ -56,15 +187,185 @@ impl DavConfig {
         self
     }
 
+    /// Serve another folder at a path of its own. A PROPFIND on `/` lists
+    /// the mounts unless the root is served there.
+    pub fn add_mount(mut self, mount: Mount) -> Self {
+        self.mounts.push(mount);
+        self
+    }
+
+    /// Replace all configured mounts.
+    pub fn mounts(mut self, mounts: Vec<Mount>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
+    /// Allow a single user, replacing any configured account.
     pub fn authorization(mut self, user: String, password: String) -> Self {
-        self.user = Some(user);
-        self.password = Some(password);
+        self.accounts = vec![Account::new(user, password)];
+        self
+    }
+
+    /// Replace all configured accounts.
+    pub fn accounts(mut self, accounts: Vec<Account>) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    pub fn add_account(mut self, account: Account) -> Self {
+        self.accounts.push(account);
         self
     }
 
     pub fn no_authorization(mut self) -> Self {
-        self.user = None;
-        self.password = None;
+        self.accounts.clear();
+        self.htpasswd = None;
+        self
+    }
+
+    /// Add the users of an Apache htpasswd file to the configured accounts.
+    /// The file is re-read whenever it changes while a server started with
+    /// it is running; a file that fails to load leaves the previous users in
+    /// place.
+    pub fn htpasswd(mut self, path: impl Into<PathBuf>) -> Self {
+        self.htpasswd = Some(path.into());
+        self
+    }
+
+    pub fn no_htpasswd(mut self) -> Self {
+        self.htpasswd = None;
+        self
+    }
+
+    /// How often the [`DavConfig::htpasswd`] file is checked for changes,
+    /// every 2 seconds by default.
+    pub fn htpasswd_poll_interval(mut self, interval: Duration) -> Self {
+        self.htpasswd_poll_interval = interval;
+        self
+    }
+
+    /// How clients log in, [`AuthScheme::Basic`] by default.
+    pub fn auth_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.auth_scheme = scheme;
+        self
+    }
+
+    /// Let `authenticator` decide who clients are instead of the accounts.
+    pub fn authenticator(self, authenticator: impl Authenticator + 'static) -> Self {
+        self.auth_scheme(AuthScheme::Custom(Arc::new(authenticator)))
+    }
+
+    /// Realm of the Basic and Digest challenges, shown by the login prompt of
+    /// some clients. `dav` by default.
+    pub fn realm(mut self, realm: String) -> Self {
+        self.realm = realm;
+        self
+    }
+
+    /// Slow down and eventually ban addresses and user names that fail to
+    /// log in too often. On by default with [`LockoutPolicy::default`].
+    pub fn lockout(mut self, policy: LockoutPolicy) -> Self {
+        self.lockout = Some(policy);
+        self
+    }
+
+    pub fn no_lockout(mut self) -> Self {
+        self.lockout = None;
+        self
+    }
+
+    /// Let clients without credentials browse and download from the root;
+    /// only requests that change something need a login.
+    pub fn anonymous_read(mut self, enable: bool) -> Self {
+        self.anonymous_read = enable;
+        self
+    }
+
+    /// Publish files without letting anyone change them, whatever the
+    /// permission of their account or share.
+    pub fn read_only(mut self, enable: bool) -> Self {
+        self.read_only = enable;
+        self
+    }
+
+    /// Refuse clients by address before they get to log in, on every
+    /// listener including the https redirect and metrics ones. Everyone is
+    /// allowed by default; [`IpFilter::private_lan`] keeps the server to the
+    /// local network.
+    pub fn ip_filter(mut self, filter: IpFilter) -> Self {
+        self.ip_filter = filter;
+        self
+    }
+
+    /// Allow clients in `net`, refusing everyone outside the allowed ranges.
+    pub fn allow(mut self, net: IpNet) -> Self {
+        self.ip_filter.allow.push(net);
+        self
+    }
+
+    /// Refuse clients in `net`, even if an allowed range contains them.
+    pub fn deny(mut self, net: IpNet) -> Self {
+        self.ip_filter.deny.push(net);
+        self
+    }
+
+    /// Accept share tokens minted with `key`, see [`ShareKey::mint`].
+    pub fn share_key(mut self, key: ShareKey) -> Self {
+        self.share_key = Some(key);
+        self
+    }
+
+    pub fn no_shares(mut self) -> Self {
+        self.share_key = None;
+        self
+    }
+
+    /// Reject the tokens of the share with `id` before they expire.
+    pub fn revoke_share(mut self, id: String) -> Self {
+        self.revoked_shares.push(id);
+        self
+    }
+
+    /// Replace the ids of all revoked shares.
+    pub fn revoked_shares(mut self, ids: Vec<String>) -> Self {
+        self.revoked_shares = ids;
+        self
+    }
+
+    /// Keep how often each share was used in `path`, so the
+    /// [`Share::max_uses`] of a share still hold after a restart.
+    pub fn share_use_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.share_use_file = Some(path.into());
+        self
+    }
+
+    pub fn no_share_use_file(mut self) -> Self {
+        self.share_use_file = None;
+        self
+    }
+
+    /// Keep the WebDAV locks in `path` so they survive a restart. Without
+    /// it locks are held in memory only.
+    pub fn lock_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lock_file = Some(path.into());
+        self
+    }
+
+    pub fn no_lock_file(mut self) -> Self {
+        self.lock_file = None;
+        self
+    }
+
+    /// Keep the properties clients set with PROPPATCH in the database at
+    /// `path` for folders without extended attributes. Without it they are
+    /// held in memory only there; elsewhere they are stored with the files.
+    pub fn property_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.property_file = Some(path.into());
+        self
+    }
+
+    pub fn no_property_file(mut self) -> Self {
+        self.property_file = None;
         self
     }
 
@@ -79,6 +380,52 @@ impl DavConfig {
         self.enable_tls = false;
         self
     }
+
+    /// Listen for plain HTTP on `bind` and redirect every request to the
+    /// first TLS listener, keeping its path. Requests outside the
+    /// [`DavConfig::http_path`] prefix are sent to the prefix.
+    pub fn https_redirect(mut self, bind: SocketAddr) -> Self {
+        self.https_redirect = vec![bind];
+        self
+    }
+
+    /// Redirect from `bind` as well, e.g. on every address the listeners
+    /// are bound to.
+    pub fn add_https_redirect(mut self, bind: SocketAddr) -> Self {
+        self.https_redirect.push(bind);
+        self
+    }
+
+    pub fn no_https_redirect(mut self) -> Self {
+        self.https_redirect.clear();
+        self
+    }
+
+    /// Serve metrics in the Prometheus text format at `path` on the dav
+    /// listeners. A file or folder at the same path is no longer reachable.
+    pub fn metrics_path(mut self, path: String) -> Self {
+        self.metrics = Some(MetricsEndpoint::Path(path));
+        self
+    }
+
+    /// Serve metrics in the Prometheus text format on a separate plain HTTP
+    /// listener, without authorization.
+    pub fn metrics_bind(mut self, bind: SocketAddr) -> Self {
+        self.metrics = Some(MetricsEndpoint::Listener(bind));
+        self
+    }
+
+    pub fn no_metrics(mut self) -> Self {
+        self.metrics = None;
+        self
+    }
+
+    /// How long in-flight requests may keep running after a shutdown has
+    /// been requested before their connections are closed.
+    pub fn shutdown_grace_period(mut self, grace_period: Duration) -> Self {
+        self.shutdown_grace_period = grace_period;
+        self
+    }
 }
 
 impl DavConfig {
@@ -90,16 +437,31 @@ impl DavConfig {
         DavServer::new(self)
     }
 
+    /// This config with the users of the htpasswd file added to its
+    /// accounts, validated.
+    fn with_htpasswd_accounts(&self) -> Result<DavConfig> {
+        let mut config = self.clone();
+        if let Some(path) = &self.htpasswd {
+            config.accounts.extend(htpasswd::load(path)?);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The path of a link that carries `token` instead of a login, e.g.
+    /// `/_share/<token>/`.
+    pub fn share_path(&self, token: &str) -> String {
+        let http_path = self.http_path.as_deref().unwrap_or("/");
+        format!("{}{}/{}/", http_path, SHARE_SEGMENT, token)
+    }
+
     pub fn validate(&self) -> Result<()> {
         if let Some(root) = &self.root {
-            let path = std::path::PathBuf::try_from(root).context("invaliable path")?;
-            if !path.exists() {
-                return Err(anyhow::anyhow!("root path not exists"));
-            }
-            if !path.is_dir() {
-                return Err(anyhow::anyhow!("root path is not a directory"));
-            }
-        } else {
+            validate_root(root)?;
+        } else if self.mounts.is_empty()
+            && !matches!(self.auth_scheme, AuthScheme::Custom(_))
+            && (self.accounts.is_empty() || self.accounts.iter().any(|a| a.root.is_none()))
+        {
             return Err(anyhow::anyhow!("root path not set"));
         }
 
@@ -109,12 +471,115 @@ impl DavConfig {
             }
         }
 
-        match (&self.user, &self.password) {
-            (Some(_), Some(_)) | (None, None) => {}
-            _ => return Err(anyhow::anyhow!("user and password must be both set or not")),
+        if let Some(MetricsEndpoint::Path(path)) = &self.metrics {
+            let http_path = self.http_path.as_deref().unwrap_or("/");
+            if !path.starts_with('/') || path == http_path {
+                return Err(anyhow::anyhow!(
+                    "metrics path must start with / and differ from the http path"
+                ));
+            }
+            if !self.mounts.is_empty()
+                && (path == "/" || self.mounts.iter().any(|m| path.starts_with(&m.path)))
+            {
+                return Err(anyhow::anyhow!("metrics path must not be a mount"));
+            }
+        }
+
+        for (i, mount) in self.mounts.iter().enumerate() {
+            mount.validate()?;
+            if self.mounts[..i].iter().any(|m| m.path == mount.path) {
+                return Err(anyhow::anyhow!("duplicate mount {}", mount.path));
+            }
+            let http_path = self.http_path.as_deref().unwrap_or("/");
+            if self.serves_http_path()
+                && (http_path.starts_with(&mount.path) || mount.path.starts_with(http_path))
+            {
+                return Err(anyhow::anyhow!(
+                    "mount {} overlaps the http path {}",
+                    mount.path,
+                    http_path
+                ));
+            }
+        }
+
+        if self.realm.is_empty()
+            || self.realm.contains(['"', '\\'])
+            || self.realm.contains(char::is_control)
+        {
+            return Err(anyhow::anyhow!("realm must not be empty or contain quotes"));
+        }
+
+        if let Some(policy) = &self.lockout {
+            if policy.threshold == 0 || policy.ban_duration.is_zero() {
+                return Err(anyhow::anyhow!(
+                    "lockout threshold and ban duration must not be zero"
+                ));
+            }
+        }
+
+        if self.share_key.is_some() && self.root.is_none() {
+            return Err(anyhow::anyhow!("shares need a root path"));
+        }
+
+        if self.anonymous_read && self.root.is_none() {
+            return Err(anyhow::anyhow!("anonymous read access needs a root path"));
+        }
+
+        for (i, account) in self.accounts.iter().enumerate() {
+            // accounts with a client certificate need no password
+            let cert_only = account.password_hash.is_empty() && account.client_cert.is_some();
+            if account.user.is_empty() || (account.password_hash.is_empty() && !cert_only) {
+                return Err(anyhow::anyhow!("user and password must not be empty"));
+            }
+            if account
+                .client_cert
+                .as_ref()
+                .is_some_and(|name| name.is_empty())
+            {
+                return Err(anyhow::anyhow!(
+                    "client certificate name of user {} must not be empty",
+                    account.user
+                ));
+            }
+            if self.accounts[..i].iter().any(|a| a.user == account.user) {
+                return Err(anyhow::anyhow!("duplicate user {}", account.user));
+            }
+            if let Some(root) = &account.root {
+                validate_root(root).with_context(|| format!("user {}", account.user))?;
+            }
+            if matches!(self.auth_scheme, AuthScheme::Digest)
+                && account.digest_ha1.is_empty()
+                && !cert_only
+            {
+                return Err(anyhow::anyhow!(
+                    "user {} has no digest credentials",
+                    account.user
+                ));
+            }
+        }
+
+        if self.listeners.is_empty() {
+            return Err(anyhow::anyhow!("at least one listener must be set"));
         }
 
-        if self.enable_tls {
+        if self.accounts.iter().any(|a| a.client_cert.is_some())
+            && self
+                .tls_config
+                .as_ref()
+                .is_none_or(|t| t.client_ca.is_none())
+        {
+            return Err(anyhow::anyhow!(
+                "client certificate accounts need a tls client ca"
+            ));
+        }
+
+        if !self.https_redirect.is_empty() && !self.listeners.iter().any(|l| self.listener_tls(l)) {
+            return Err(anyhow::anyhow!(
+                "https redirect needs a listener with tls enabled"
+            ));
+        }
+
+        if self.listeners.iter().any(|l| self.listener_tls(l)) {
             if let Some(tls_config) = &self.tls_config {
                 match &tls_config.cert {
                     Certificate::Pem { cert, key } => {
@@ -127,6 +592,11 @@ impl DavConfig {
                             return Err(anyhow::anyhow!("tls cert and key must not be empty"));
                         }
                     }
+                    Certificate::PemFile { cert, key } => {
+                        if cert.as_os_str().is_empty() || key.as_os_str().is_empty() {
+                            return Err(anyhow::anyhow!("tls cert and key path must not be empty"));
+                        }
+                    }
                 }
             } else {
                 return Err(anyhow::anyhow!(
@@ -139,146 +609,1162 @@ impl DavConfig {
     }
 }
 
+fn validate_root(root: &str) -> Result<()> {
+    let path = std::path::PathBuf::from(root);
+    if !path.exists() {
+        return Err(anyhow::anyhow!("root path not exists"));
+    }
+    if !path.is_dir() {
+        return Err(anyhow::anyhow!("root path is not a directory"));
+    }
+    Ok(())
+}
+
+impl DavConfig {
+    /// Whether folders are served at the http path; with mounts only if a
+    /// root is set for it.
+    fn serves_http_path(&self) -> bool {
+        self.mounts.is_empty()
+            || self.root.is_some()
+            || self.accounts.iter().any(|a| a.root.is_some())
+    }
+
+    /// Config of the router serving `mount`, which takes the accounts and
+    /// settings of this config.
+    fn mount_config(&self, mount: &Mount) -> DavConfig {
+        let public = mount.access == MountAccess::Public;
+        DavConfig {
+            root: Some(mount.root.clone()),
+            http_path: Some(mount.path.clone()),
+            mounts: Vec::new(),
+            accounts: match public {
+                true => Vec::new(),
+                false => self
+                    .accounts
+                    .iter()
+                    .map(|account| Account {
+                        root: None,
+                        ..account.clone()
+                    })
+                    .collect(),
+            },
+            auth_scheme: match public {
+                true => AuthScheme::Basic,
+                false => self.auth_scheme.clone(),
+            },
+            anonymous_read: mount.access == MountAccess::AnonymousRead,
+            read_only: self.read_only || mount.read_only,
+            share_key: None,
+            revoked_shares: Vec::new(),
+            metrics: None,
+            ..self.clone()
+        }
+    }
+
+    fn listener_tls(&self, listener: &Listener) -> bool {
+        listener.tls.unwrap_or(self.enable_tls)
+    }
+
+    /// Whether `other` can be applied to a server bound with `self`.
+    fn same_binding(&self, other: &DavConfig) -> bool {
+        let metrics_bind = |c: &DavConfig| match c.metrics {
+            Some(MetricsEndpoint::Listener(bind)) => Some(bind),
+            _ => None,
+        };
+        let listeners = |c: &DavConfig| {
+            c.listeners
+                .iter()
+                .map(|l| (l.bind, c.listener_tls(l)))
+                .collect::<Vec<_>>()
+        };
+
+        listeners(self) == listeners(other)
+            && self.https_redirect == other.https_redirect
+            && metrics_bind(self) == metrics_bind(other)
+            && self.tls_config == other.tls_config
+            && self.shutdown_grace_period == other.shutdown_grace_period
+    }
+}
+
+/// An address the server listens on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listener {
+    bind: SocketAddr,
+    tls: Option<bool>,
+}
+
+impl Listener {
+    /// A listener that uses TLS only if it is enabled on the [`DavConfig`].
+    pub fn new(bind: SocketAddr) -> Self {
+        Listener { bind, tls: None }
+    }
+
+    /// Turn TLS on or off for this listener regardless of the [`DavConfig`]
+    /// default. The certificate is still the one set by [`DavConfig::tls`].
+    pub fn tls(mut self, enable: bool) -> Self {
+        self.tls = Some(enable);
+        self
+    }
+
+    pub fn bind(&self) -> SocketAddr {
+        self.bind
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct TlsConfig {
     cert: Certificate,
+    watch_interval: Option<Duration>,
+    client_ca: Option<ClientCa>,
+    require_client_cert: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum Certificate {
     Pem { cert: Vec<u8>, key: Vec<u8> },
     Der { cert: Vec<Vec<u8>>, key: Vec<u8> },
+    PemFile { cert: PathBuf, key: PathBuf },
+}
+
+impl std::fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Certificate::Pem { cert, .. } => f
+                .debug_struct("Pem")
+                .field("cert", &String::from_utf8_lossy(cert))
+                .field("key", &REDACTED)
+                .finish(),
+            Certificate::Der { cert, .. } => f
+                .debug_struct("Der")
+                .field("cert", cert)
+                .field("key", &REDACTED)
+                .finish(),
+            Certificate::PemFile { cert, key } => f
+                .debug_struct("PemFile")
+                .field("cert", cert)
+                .field("key", key)
+                .finish(),
+        }
+    }
 }
 
 impl TlsConfig {
     pub fn pem(cert: Vec<u8>, key: Vec<u8>) -> Self {
         TlsConfig {
             cert: Certificate::Pem { cert, key },
+            watch_interval: None,
+            client_ca: None,
+            require_client_cert: false,
         }
     }
 
     pub fn der(cert: Vec<Vec<u8>>, key: Vec<u8>) -> Self {
         TlsConfig {
             cert: Certificate::Der { cert, key },
+            watch_interval: None,
+            client_ca: None,
+            require_client_cert: false,
         }
     }
-}
-
-#[derive(Debug)]
-pub struct DavServer {
-    config: DavConfig,
-}
 
-impl DavServer {
-    pub fn new(config: DavConfig) -> Self {
-        DavServer { config }
+    /// Read the PEM encoded cert and key from files when the server is bound.
+    pub fn pem_file(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        TlsConfig {
+            cert: Certificate::PemFile {
+                cert: cert.into(),
+                key: key.into(),
+            },
+            watch_interval: None,
+            client_ca: None,
+            require_client_cert: false,
+        }
     }
 
-    pub fn builder() -> DavConfig {
-        DavConfig::new()
+    /// Check the cert and key files for changes every `interval` while the
+    /// server is running and reload them when they change. A cert that fails
+    /// to load is skipped and the previous one stays in use. Only has an
+    /// effect with [`TlsConfig::pem_file`].
+    pub fn watch(mut self, interval: Duration) -> Self {
+        self.watch_interval = Some(interval);
+        self
     }
 
-    pub async fn run(&self) -> Result<()> {
-        self.config.validate()?;
-
-        let path_prefix = self.config.http_path.as_deref().unwrap_or("/");
-        let account = match (&self.config.user, &self.config.password) {
-            (Some(user), Some(password)) => Some(Account::new(user.clone(), password.clone())),
-            _ => None,
-        };
-
-        let dav_service_handler = dav_server::DavHandler::builder()
-            .strip_prefix(path_prefix)
-            .filesystem(LocalFs::new(
-                self.config.root.clone().unwrap(),
-                false,
-                false,
-                false,
-            ))
-            .locksystem(FakeLs::new())
-            .build_handler();
-        let dav_router = axum::Router::new()
-            .route_service(
-                path_prefix,
-                service_fn(move |req| {
-                    let dav_service_handler = dav_service_handler.clone();
-                    async move { Ok(dav_service_handler.handle(req).await) }
-                }),
-            )
-            .with(|r| match account {
-                None => r,
-                Some(account) => r.route_layer(axum::middleware::from_fn_with_state(
-                    account,
-                    http_basic_authorize_middleware,
-                )),
-            });
+    /// Ask clients for a certificate and verify it against the PEM encoded
+    /// CA certificates in `ca`. Clients with a certificate that matches an
+    /// [`Account::client_cert`] are logged in to that account.
+    pub fn client_ca_pem(mut self, ca: Vec<u8>) -> Self {
+        self.client_ca = Some(ClientCa::Pem(ca));
+        self
+    }
 
-        if self.config.enable_tls {
-            let TlsConfig { cert } = self.config.tls_config.clone().unwrap();
+    /// Like [`TlsConfig::client_ca_pem`], reading the CA certificates from
+    /// `path` whenever the certificate is loaded.
+    pub fn client_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.client_ca = Some(ClientCa::PemFile(path.into()));
+        self
+    }
 
-            let tls_config = match cert {
-                Certificate::Pem { cert, key } => RustlsConfig::from_pem(cert, key).await,
-                Certificate::Der { cert, key } => RustlsConfig::from_der(cert, key).await,
-            }
-            .context("failed to load tls cert")?;
+    /// Refuse connections of clients without a certificate signed by the
+    /// client CA, instead of leaving them to the password authentication.
+    pub fn require_client_cert(mut self, require: bool) -> Self {
+        self.require_client_cert = require;
+        self
+    }
+}
 
-            axum_server::bind_rustls(self.config.bind.clone(), tls_config)
-                .serve(dav_router.into_make_service())
+/// Load the cert and client CA of `tls_config` into a new [`RustlsConfig`].
+/// Errors if either is broken or carries no certificate, so a failed reload
+/// never replaces a working cert.
+async fn load_rustls_config(tls_config: &TlsConfig) -> Result<RustlsConfig> {
+    let (cert, key) = match tls_config.cert.clone() {
+        Certificate::Pem { cert, key } => pem_to_der(&cert, &key)?,
+        Certificate::Der { cert, key } => (cert, key),
+        Certificate::PemFile { cert, key } => {
+            let cert = tokio::fs::read(&cert)
                 .await
-                .context("failed to start dav server")
-        } else {
-            axum::Server::bind(&self.config.bind)
-                .serve(dav_router.into_make_service())
+                .with_context(|| format!("failed to read {}", cert.display()))?;
+            let key = tokio::fs::read(&key)
                 .await
-                .context("failed to start dav server")
+                .with_context(|| format!("failed to read {}", key.display()))?;
+            pem_to_der(&cert, &key)?
         }
+    };
+    if cert.is_empty() {
+        return Err(anyhow::anyhow!("tls cert contains no certificate"));
     }
+
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = match &tls_config.client_ca {
+        Some(ca) => {
+            builder.with_client_cert_verifier(ca.verifier(tls_config.require_client_cert).await?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder
+        .with_single_cert(
+            cert.into_iter().map(rustls::Certificate).collect(),
+            rustls::PrivateKey(key),
+        )
+        .context("failed to load tls cert")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(RustlsConfig::from_config(Arc::new(server_config)))
+}
+
+fn pem_to_der(cert: &[u8], key: &[u8]) -> Result<(Vec<Vec<u8>>, Vec<u8>)> {
+    use rustls_pemfile::Item;
+
+    let cert = rustls_pemfile::certs(&mut &*cert).context("failed to parse tls cert")?;
+    let key = rustls_pemfile::read_all(&mut &*key)
+        .context("failed to parse tls key")?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .context("tls key not found")?;
+    Ok((cert, key))
 }
 
+/// Handle to request a graceful shutdown of a [`DavServer`] and to wait for
+/// it to finish.
 #[derive(Debug, Clone)]
-struct Account {
-    user: String,
-    password: String,
+pub struct ShutdownHandle {
+    shutdown: CancellationToken,
+    stopped: CancellationToken,
 }
 
-impl Account {
-    fn new(user: String, password: String) -> Self {
-        Account { user, password }
+impl ShutdownHandle {
+    fn new() -> Self {
+        ShutdownHandle {
+            shutdown: CancellationToken::new(),
+            stopped: CancellationToken::new(),
+        }
     }
-}
 
-async fn http_basic_authorize_middleware<B>(
-    State(account): State<Account>,
-    TypedHeader(auth): TypedHeader<Authorization<axum::headers::authorization::Basic>>,
-    req: Request<B>,
-    next: Next<B>,
-) -> Response {
-    let req_user = auth.username();
-    let req_password = auth.password();
+    /// Stop accepting new connections. Requests already in flight are given
+    /// the configured grace period to complete.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
 
-    let Account { user, password } = account;
-    if user == req_user && password == req_password {
-        next.run(req).await
-    } else {
-        (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+    /// Resolves once the server has stopped, or failed to start.
+    pub async fn stopped(&self) {
+        self.stopped.cancelled().await;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    #[ignore]
-    #[tokio::test]
-    async fn test_dav_server() {
-        use super::*;
-        println!("pwd: {:?}", std::env::current_dir().unwrap());
-        let server = DavServer::builder()
-            .root("../public".to_string())
-            .bind("127.0.0.1:8080".parse().unwrap())
-            .build();
-        server.run().await.unwrap();
+    /// Request a shutdown and wait until the server has stopped.
+    pub async fn shutdown_and_wait(&self) {
+        self.shutdown();
+        self.stopped().await;
     }
 
-    #[ignore]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.is_cancelled()
+    }
+}
+
+/// Result of [`DavServer::reload`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadStatus {
+    /// The new config is live.
+    Applied,
+    /// The new config changes the listeners, the TLS settings or the
+    /// shutdown grace period. Nothing was applied; the server has to be
+    /// restarted for the change to take effect. A new certificate alone can
+    /// be applied with [`DavServer::reload_tls`].
+    RebindRequired,
+}
+
+#[derive(Debug)]
+pub struct DavServer {
+    config: Arc<ArcSwap<DavConfig>>,
+    router: Arc<Mutex<axum::Router>>,
+    rustls_config: Mutex<Option<RustlsConfig>>,
+    state: ServerState,
+    events: broadcast::Sender<DavEvent>,
+    shutdown: ShutdownHandle,
+}
+
+/// The parts of a [`DavServer`] its routers share. They are kept when the
+/// router is rebuilt, so a reload loses no locks, bans or Digest nonces.
+#[derive(Debug, Clone, Default)]
+struct ServerState {
+    metrics: Arc<Metrics>,
+    lockouts: Arc<Lockouts>,
+    share_uses: Arc<ShareUses>,
+    locks: Arc<Locks>,
+    properties: Arc<Properties>,
+    digest_keys: Arc<DigestKeys>,
+}
+
+impl ServerState {
+    /// Switch to the lock, property and share use files of `config` that
+    /// differ from those of `current`. All of them are read before any is
+    /// switched, so a file that fails to load leaves the others as they were.
+    fn open_files(&self, current: Option<&DavConfig>, config: &DavConfig) -> Result<()> {
+        let changed = |file: fn(&DavConfig) -> &Option<PathBuf>| {
+            current.is_none_or(|current| file(current) != file(config))
+        };
+        let locks = changed(|c| &c.lock_file)
+            .then(|| Locks::load(config.lock_file.as_deref()))
+            .transpose()?;
+        let properties = match changed(|c| &c.property_file) {
+            true => self.properties.load(config.property_file.as_deref())?,
+            false => None,
+        };
+        let share_uses = changed(|c| &c.share_use_file)
+            .then(|| ShareUses::load(config.share_use_file.as_deref()))
+            .transpose()?;
+
+        // the only switch that can fail goes first
+        if let Some(file) = properties {
+            self.properties.switch(file)?;
+        }
+        if let Some(file) = locks {
+            self.locks.switch(file);
+        }
+        if let Some(file) = share_uses {
+            self.share_uses.switch(file);
+        }
+        Ok(())
+    }
+}
+
+impl DavServer {
+    pub fn new(config: DavConfig) -> Self {
+        DavServer {
+            config: Arc::new(ArcSwap::from_pointee(config)),
+            router: Arc::new(Mutex::new(axum::Router::new())),
+            rustls_config: Mutex::new(None),
+            state: ServerState::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+
+    pub fn builder() -> DavConfig {
+        DavConfig::new()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.state.metrics.clone()
+    }
+
+    /// The addresses and user names currently banned for failing to log in
+    /// too often.
+    pub fn bans(&self) -> Vec<Ban> {
+        match &self.config.load().lockout {
+            Some(policy) => self.state.lockouts.bans(policy),
+            None => Vec::new(),
+        }
+    }
+
+    /// Lift the ban of `target` and forget its failed logins. Returns
+    /// whether there were any.
+    pub fn clear_ban(&self, target: &BanTarget) -> bool {
+        self.state.lockouts.clear(target)
+    }
+
+    pub fn clear_bans(&self) {
+        self.state.lockouts.clear_all();
+    }
+
+    /// How many files have been transferred with the tokens of the share
+    /// with `id`.
+    pub fn share_uses(&self, id: &str) -> u32 {
+        self.state.share_uses.get(id)
+    }
+
+    /// Receive the [`DavEvent`]s published from now on. A receiver that
+    /// falls too far behind skips the oldest events.
+    pub fn subscribe(&self) -> broadcast::Receiver<DavEvent> {
+        self.events.subscribe()
+    }
+
+    /// Bind the listener and serve until the server is shut down.
+    pub async fn run(&self) -> Result<()> {
+        self.bind().await?.serve().await
+    }
+
+    /// Validate the config, load the TLS certificate and bind the listener
+    /// without serving any request yet. Errors that would prevent the server
+    /// from starting are reported here.
+    pub async fn bind(&self) -> Result<BoundDavServer> {
+        let stopped = self.shutdown.stopped.clone().drop_guard();
+        let config = self.config.load_full();
+        config.validate()?;
+
+        self.state.open_files(None, &config)?;
+        *self.router.lock().unwrap() = build_router(&config.with_htpasswd_accounts()?, &self.state);
+
+        let tls_config = if config.listeners.iter().any(|l| config.listener_tls(l)) {
+            let tls_config = config.tls_config.as_ref().unwrap();
+            Some(load_rustls_config(tls_config).await?)
+        } else {
+            None
+        };
+        *self.rustls_config.lock().unwrap() = tls_config.clone();
+
+        let router = dispatch_router(self.router.clone())
+            .layer(axum::middleware::from_fn_with_state(
+                self.state.metrics.clone(),
+                metrics_middleware,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                self.events.clone(),
+                events_middleware,
+            ))
+            .layer(axum::middleware::from_fn(access_log_middleware));
+        let mut listeners = Vec::with_capacity(config.listeners.len());
+        for l in &config.listeners {
+            let (listener, local_addr) = bind_listener(l.bind)?;
+            listeners.push(BoundListener {
+                listener,
+                local_addr,
+                tls: config.listener_tls(l),
+                router: router.clone(),
+            });
+        }
+
+        // the dav router filters by itself, the others need it done for them
+        let ip_filter =
+            axum::middleware::from_fn_with_state(self.config.clone(), config_ip_filter_middleware);
+        let https_port = listeners
+            .iter()
+            .find(|l| l.tls)
+            .map(|l| l.local_addr.port());
+        for &bind in &config.https_redirect {
+            // validated to have a tls listener
+            let https_port = https_port.unwrap();
+            let (listener, local_addr) = bind_listener(bind)?;
+            listeners.push(BoundListener {
+                listener,
+                local_addr,
+                tls: false,
+                router: https_redirect_router(self.config.clone(), https_port)
+                    .layer(ip_filter.clone()),
+            });
+        }
+
+        if let Some(MetricsEndpoint::Listener(bind)) = config.metrics {
+            let (listener, local_addr) = bind_listener(bind)?;
+            let metrics = self.state.metrics.clone();
+            listeners.push(BoundListener {
+                listener,
+                local_addr,
+                tls: false,
+                router: axum::Router::new()
+                    .fallback(move || async move { metrics.render() })
+                    .layer(ip_filter),
+            });
+        }
+
+        let tls_watch = tls_config.as_ref().and_then(|tls_config| {
+            let interval = config.tls_config.as_ref()?.watch_interval?;
+            Some(TlsWatch {
+                config: self.config.clone(),
+                rustls_config: tls_config.clone(),
+                interval,
+            })
+        });
+
+        let htpasswd_watch = config.htpasswd.is_some().then(|| HtpasswdWatch {
+            config: self.config.clone(),
+            router: self.router.clone(),
+            state: self.state.clone(),
+        });
+
+        Ok(BoundDavServer {
+            listeners,
+            tls_config,
+            tls_watch,
+            htpasswd_watch,
+            state: self.state.clone(),
+            events: self.events.clone(),
+            shutdown: self.shutdown.shutdown.clone(),
+            grace_period: config.shutdown_grace_period,
+            _stopped: stopped,
+        })
+    }
+
+    /// Apply `config` to the server without dropping its listeners. The
+    /// root, path prefix and credentials are swapped in place, requests
+    /// already in flight finish with the old ones.
+    pub fn reload(&self, config: DavConfig) -> Result<ReloadStatus> {
+        config.validate()?;
+
+        if !self.config.load().same_binding(&config) {
+            return Ok(ReloadStatus::RebindRequired);
+        }
+
+        let router = build_router(&config.with_htpasswd_accounts()?, &self.state);
+        self.state.open_files(Some(&self.config.load()), &config)?;
+        let mut current = self.router.lock().unwrap();
+        *current = router;
+        // under the router lock, so the htpasswd watch cannot swap in a
+        // router of the old config after this
+        self.config.store(Arc::new(config));
+        Ok(ReloadStatus::Applied)
+    }
+
+    /// Replace the TLS certificate of the running server. Connections that
+    /// are already established keep the old one. If the new certificate
+    /// cannot be loaded an error is returned and the old one stays in use.
+    pub async fn reload_tls(&self, tls_config: TlsConfig) -> Result<()> {
+        let new_config = load_rustls_config(&tls_config).await?;
+
+        if let Some(rustls_config) = self.rustls_config.lock().unwrap().as_ref() {
+            rustls_config.reload_from_config(new_config.get_inner());
+        }
+        self.config.rcu(|config| {
+            let mut config = DavConfig::clone(config);
+            config.tls_config = Some(tls_config.clone());
+            config
+        });
+        Ok(())
+    }
+}
+
+/// Polls the cert and key files of the current [`TlsConfig`] and reloads them
+/// into the running server when they change.
+#[derive(Debug)]
+struct TlsWatch {
+    config: Arc<ArcSwap<DavConfig>>,
+    rustls_config: RustlsConfig,
+    interval: Duration,
+}
+
+type CertFileStamp = (TlsConfig, Option<SystemTime>, Option<SystemTime>);
+
+impl TlsWatch {
+    fn stamp(&self) -> Option<CertFileStamp> {
+        let config = self.config.load();
+        let tls_config = config.tls_config.as_ref()?;
+        let Certificate::PemFile { cert, key } = &tls_config.cert else {
+            return None;
+        };
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Some((tls_config.clone(), modified(cert), modified(key)))
+    }
+
+    async fn run(self) {
+        let mut last = self.stamp();
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            let stamp = self.stamp();
+            if stamp == last {
+                continue;
+            }
+            last = stamp;
+
+            let Some((tls_config, _, _)) = &last else {
+                continue;
+            };
+            // a half written pair fails to load; it is retried once the other
+            // file changes as well
+            match load_rustls_config(tls_config).await {
+                Ok(new_config) => self
+                    .rustls_config
+                    .reload_from_config(new_config.get_inner()),
+                Err(err) => tracing::warn!("failed to reload tls cert: {:#}", err),
+            }
+        }
+    }
+}
+
+/// Polls the [`DavConfig::htpasswd`] file of the current config and rebuilds
+/// the router when it changes.
+#[derive(Debug)]
+struct HtpasswdWatch {
+    config: Arc<ArcSwap<DavConfig>>,
+    router: Arc<Mutex<axum::Router>>,
+    state: ServerState,
+}
+
+/// The htpasswd file of `config` and when it was last changed.
+fn htpasswd_stamp(config: &DavConfig) -> Option<(PathBuf, Option<SystemTime>)> {
+    let path = config.htpasswd.clone()?;
+    let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    Some((path, modified))
+}
+
+impl HtpasswdWatch {
+    async fn stamp(&self) -> Option<(PathBuf, Option<SystemTime>)> {
+        let config = self.config.load_full();
+        tokio::task::spawn_blocking(move || htpasswd_stamp(&config))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn run(self) {
+        let mut last = self.stamp().await;
+        loop {
+            tokio::time::sleep(self.config.load().htpasswd_poll_interval).await;
+
+            let stamp = self.stamp().await;
+            if stamp.is_none() || stamp == last {
+                continue;
+            }
+            last = stamp;
+
+            // reading the file and hashing the dummy password take a while
+            let config = self.config.load_full();
+            let state = self.state.clone();
+            let built = tokio::task::spawn_blocking({
+                let config = config.clone();
+                move || {
+                    let config = config.with_htpasswd_accounts()?;
+                    Ok::<_, anyhow::Error>(build_router(&config, &state))
+                }
+            })
+            .await;
+            match built {
+                Ok(Ok(router)) => {
+                    let mut current = self.router.lock().unwrap();
+                    // a reload since has read the file as well, with a newer config
+                    if Arc::ptr_eq(&config, &self.config.load()) {
+                        *current = router;
+                    }
+                }
+                Ok(Err(err)) => tracing::warn!("failed to reload htpasswd file: {:#}", err),
+                Err(err) => tracing::warn!("failed to reload htpasswd file: {}", err),
+            }
+        }
+    }
+}
+
+fn build_router(config: &DavConfig, state: &ServerState) -> axum::Router {
+    let ip_filter = (!config.ip_filter.is_empty()).then(|| Arc::new(config.ip_filter.clone()));
+    let mut router = dav_router(config, false, state);
+    for mount in &config.mounts {
+        let mount_config = config.mount_config(mount);
+        router = router.merge(dav_router(&mount_config, true, state));
+    }
+    if !config.mounts.is_empty() {
+        router = router.merge(mount_list_router(&config.mounts));
+    }
+    // refuse clients before they get to the authentication
+    router.with(|r| match ip_filter {
+        Some(ip_filter) => r.layer(axum::middleware::from_fn_with_state(
+            ip_filter,
+            ip_filter_middleware,
+        )),
+        None => r,
+    })
+}
+
+/// The file system and the paths of a COPY of `root` served at `prefix`.
+/// dav-server leaves the properties of copied folders behind, they are
+/// copied once the copy succeeded.
+fn folder_copy(
+    req: &Request<Body>,
+    properties: &Arc<Properties>,
+    prefix: &str,
+    root: &str,
+) -> Option<(Box<PropFs>, DavPath, DavPath, bool)> {
+    if req.method().as_str() != "COPY" {
+        return None;
+    }
+    let path = |path: &str| {
+        let mut path = DavPath::new(path).ok()?;
+        path.set_prefix(prefix).ok()?;
+        Some(path)
+    };
+    let from = path(req.uri().path())?;
+    let to = req
+        .headers()
+        .get("Destination")
+        .and_then(|d| d.to_str().ok())
+        .and_then(|d| d.parse::<axum::http::Uri>().ok())
+        .and_then(|d| path(d.path()))?;
+    let deep = req.headers().get("Depth").is_none_or(|d| d != "0");
+    Some((properties.file_system(root), from, to, deep))
+}
+
+/// Router serving the root of `config` at its http path, behind its
+/// authentication. Below a mount the folders of the identities are ignored.
+fn dav_router(config: &DavConfig, mount: bool, state: &ServerState) -> axum::Router {
+    let serves_http_path = config.serves_http_path();
+    let path_prefix = config.http_path.as_deref().unwrap_or("/");
+    let metrics_path = match &config.metrics {
+        Some(MetricsEndpoint::Path(path)) => Some(path.clone()),
+        _ => None,
+    };
+    let metrics = state.metrics.clone();
+
+    let read_only = config.read_only;
+    let anonymous_read = config.anonymous_read;
+    // the method set also decides what OPTIONS advertises in `Allow`
+    let methods = match read_only {
+        true => DavMethodSet::WEBDAV_RO,
+        false => DavMethodSet::WEBDAV_RW,
+    };
+    let locks = state.locks.clone();
+    let properties = state.properties.clone();
+    let copy_properties = state.properties.clone();
+    let dav_handler = move |prefix: &str, root: &str| {
+        dav_server::DavHandler::builder()
+            .strip_prefix(prefix)
+            .filesystem(properties.file_system(root))
+            .locksystem(locks.lock_system(root, prefix))
+            .methods(methods)
+            .build_handler()
+    };
+    let default_handler = config
+        .root
+        .as_deref()
+        .map(|root| dav_handler(path_prefix, root));
+    // handlers of other prefixes and roots, built when first needed, with
+    // the expiry of the share link they serve
+    let handlers = Arc::new(Mutex::new(HashMap::<
+        (String, String),
+        (DavHandler, Option<SystemTime>),
+    >::new()));
+    let authenticator: Option<Arc<dyn Authenticator>> = match &config.auth_scheme {
+        AuthScheme::Custom(authenticator) => Some(authenticator.clone()),
+        _ if config.accounts.is_empty() => None,
+        AuthScheme::Basic => Some(Arc::new(BasicAuthenticator::new(
+            config.accounts.clone(),
+            &config.realm,
+        ))),
+        AuthScheme::Digest => Some(Arc::new(DigestAuthenticator::new(
+            config.accounts.clone(),
+            config.realm.clone(),
+            state.digest_keys.clone(),
+        ))),
+    };
+    let shares = config
+        .share_key
+        .clone()
+        .zip(config.root.clone())
+        .map(|(key, root)| ShareAuth {
+            key,
+            root,
+            segment_prefix: format!("{}{}/", path_prefix, SHARE_SEGMENT),
+            revoked: config.revoked_shares.iter().cloned().collect(),
+            uses: state.share_uses.clone(),
+        });
+    let client_cert_accounts = config
+        .accounts
+        .iter()
+        .filter(|account| account.client_cert.is_some())
+        .cloned()
+        .collect::<Vec<_>>();
+    let authentication = (authenticator.is_some() || shares.is_some()).then(|| {
+        Arc::new(Authentication {
+            authenticator,
+            shares,
+            client_cert_accounts,
+            anonymous_read: config.anonymous_read,
+            lockouts: state.lockouts.clone(),
+            lockout: config.lockout,
+        })
+    });
+
+    let default_prefix = path_prefix.to_string();
+    let default_root = config.root.clone();
+    let dav_service = service_fn(move |mut req: Request<Body>| {
+        let identity = req.extensions().get::<Identity>();
+        let permission = match identity {
+            _ if read_only => Permission::ReadOnly,
+            Some(identity) => identity.permission,
+            // with no accounts to log in with, anonymous clients still only read
+            None if anonymous_read => Permission::ReadOnly,
+            None => Permission::Full,
+        };
+        let root = identity.and_then(|i| i.root.as_deref()).filter(|_| !mount);
+        // locks belong to the user who took them
+        let lock_config = match identity {
+            Some(identity) => dav_server::DavConfig::new().principal(identity.user.clone()),
+            None => dav_server::DavConfig::new(),
+        };
+        let share_prefix = req.extensions().get::<SharePrefix>();
+        let prefix = share_prefix.map(|p| p.prefix.as_str());
+        let handler = match (root, prefix) {
+            (None, None) => default_handler.clone(),
+            (Some(root), prefix) => {
+                let prefix = prefix.unwrap_or(&default_prefix);
+                let expires = share_prefix.map(|p| p.expires);
+                let mut handlers = handlers.lock().unwrap();
+                // revoking a share rebuilds the router, expiring does not
+                let now = SystemTime::now();
+                handlers.retain(|_, (_, expires)| expires.is_none_or(|e| e > now));
+                Some(
+                    handlers
+                        .entry((prefix.to_string(), root.to_string()))
+                        .or_insert_with(|| (dav_handler(prefix, root), expires))
+                        .0
+                        .clone(),
+                )
+            }
+            // shares always come with a root
+            (None, Some(_)) => None,
+        };
+        let folder_copy = root.or(default_root.as_deref()).and_then(|root| {
+            folder_copy(
+                &req,
+                &copy_properties,
+                prefix.unwrap_or(&default_prefix),
+                root,
+            )
+        });
+        async move {
+            let forbidden = || {
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body("Forbidden".into())
+                    .unwrap()
+            };
+            // only a custom authenticator can leave a request without a root
+            let Some(handler) = handler else {
+                return Ok(forbidden());
+            };
+            if !permission.allows(req.method()) {
+                return Ok(forbidden());
+            }
+            // dav-server creates the file of a PUT with `If-None-Match: *` only
+            // if it does not exist yet, in the same step it opens it
+            let upload_only = permission == Permission::UploadOnly && req.method() == Method::PUT;
+            if upload_only {
+                req.headers_mut()
+                    .insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+            }
+            let resp = handler.handle_with(lock_config, req).await;
+            if upload_only && resp.status() == StatusCode::PRECONDITION_FAILED {
+                return Ok(forbidden());
+            }
+            if let Some((fs, from, to, deep)) = folder_copy.filter(|_| resp.status().is_success()) {
+                fs.copy_folder_props(&from, &to, deep).await;
+            }
+            Ok(resp)
+        }
+    });
+    let has_routes = serves_http_path || metrics_path.is_some();
+    let dav_routes = serves_http_path.then(|| (path_prefix.to_string(), dav_service));
+    axum::Router::new()
+        .with(|r| match dav_routes {
+            // the prefix itself and everything below it
+            Some((prefix, service)) => r
+                .route_service(&prefix, service.clone())
+                .route_service(&format!("{}*path", prefix), service),
+            None => r,
+        })
+        .with(|r| match metrics_path {
+            Some(path) => r.route(&path, get(move || async move { metrics.render() })),
+            None => r,
+        })
+        .with(move |r| match authentication {
+            // without routes there is nothing to authenticate
+            Some(authentication) if has_routes => r.route_layer(
+                axum::middleware::from_fn_with_state(authentication, authenticate_middleware),
+            ),
+            _ => r,
+        })
+}
+
+/// Router handed to the listeners; it forwards every request to whatever
+/// router is current, so [`DavServer::reload`] takes effect immediately.
+fn dispatch_router(router: Arc<Mutex<axum::Router>>) -> axum::Router {
+    axum::Router::new().fallback_service(service_fn(move |req: Request<Body>| {
+        let router = router.lock().unwrap().clone();
+        router.oneshot(req)
+    }))
+}
+
+/// Router for the [`DavConfig::https_redirect`] listener. `https_port` is the
+/// port of the first TLS listener.
+fn https_redirect_router(config: Arc<ArcSwap<DavConfig>>, https_port: u16) -> axum::Router {
+    axum::Router::new().fallback_service(service_fn(move |req: Request<Body>| {
+        let response = https_redirect(&config.load(), https_port, &req);
+        async move { Ok::<_, std::convert::Infallible>(response) }
+    }))
+}
+
+fn https_redirect<B>(config: &DavConfig, https_port: u16, req: &Request<B>) -> Response {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .or_else(|| req.uri().authority().cloned());
+    let Some(host) = host else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+
+    let path_prefix = config.http_path.as_deref().unwrap_or("/");
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    let path = if path.starts_with(path_prefix)
+        || path_prefix.strip_suffix('/') == Some(path)
+        || config.mounts.iter().any(|m| path.starts_with(&m.path))
+    {
+        path
+    } else {
+        path_prefix
+    };
+    let location = match https_port {
+        443 => format!("https://{}{}", host.host(), path),
+        port => format!("https://{}:{}{}", host.host(), port, path),
+    };
+
+    // 308 keeps the method and body, which WebDAV clients rely on
+    let status = if req.method() == Method::GET || req.method() == Method::HEAD {
+        StatusCode::MOVED_PERMANENTLY
+    } else {
+        StatusCode::PERMANENT_REDIRECT
+    };
+    (status, [(header::LOCATION, location)]).into_response()
+}
+
+/// A [`DavServer`] whose listeners are bound, returned by [`DavServer::bind`].
+#[derive(Debug)]
+pub struct BoundDavServer {
+    listeners: Vec<BoundListener>,
+    tls_config: Option<RustlsConfig>,
+    tls_watch: Option<TlsWatch>,
+    htpasswd_watch: Option<HtpasswdWatch>,
+    state: ServerState,
+    events: broadcast::Sender<DavEvent>,
+    shutdown: CancellationToken,
+    grace_period: Duration,
+    _stopped: DropGuard,
+}
+
+#[derive(Debug)]
+struct BoundListener {
+    listener: std::net::TcpListener,
+    local_addr: SocketAddr,
+    tls: bool,
+    router: axum::Router,
+}
+
+fn bind_listener(bind: SocketAddr) -> Result<(std::net::TcpListener, SocketAddr)> {
+    let listener =
+        std::net::TcpListener::bind(bind).with_context(|| format!("failed to bind {}", bind))?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+    Ok((listener, local_addr))
+}
+
+impl BoundDavServer {
+    /// The addresses the listeners are actually bound to, in the order they
+    /// were configured, followed by the https redirect and the metrics
+    /// listener if any. Useful when binding to port 0.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().map(|l| l.local_addr).collect()
+    }
+
+    /// Serve on every listener until the server is shut down or one of the
+    /// listeners fails.
+    pub async fn serve(self) -> Result<()> {
+        let BoundDavServer {
+            listeners,
+            tls_config,
+            tls_watch,
+            htpasswd_watch,
+            state,
+            events,
+            shutdown,
+            grace_period,
+            _stopped,
+        } = self;
+
+        let watch_task = tls_watch.map(|watch| tokio::spawn(watch.run()));
+        let htpasswd_task = htpasswd_watch.map(|watch| tokio::spawn(watch.run()));
+
+        let servers = listeners.into_iter().map(|l| {
+            let tls_config = if l.tls { tls_config.clone() } else { None };
+            let make_service = MakeConnectionService {
+                inner: l.router,
+                metrics: state.metrics.clone(),
+                events: events.clone(),
+            };
+            serve_listener(
+                l.listener,
+                tls_config,
+                make_service,
+                shutdown.clone(),
+                grace_period,
+            )
+        });
+        let result = futures_util::future::try_join_all(servers).await;
+        if let Some(watch_task) = watch_task {
+            watch_task.abort();
+        }
+        if let Some(htpasswd_task) = htpasswd_task {
+            htpasswd_task.abort();
+        }
+        // a server started next may read the files right away
+        let locks = state.locks.clone();
+        let properties = state.properties.clone();
+        let share_uses = state.share_uses.clone();
+        tokio::task::spawn_blocking(move || {
+            locks.flush();
+            if let Err(e) = properties.close() {
+                tracing::warn!("failed to close the property file: {:#}", e);
+            }
+            share_uses.flush();
+        })
+        .await?;
+        result?;
+        Ok(())
+    }
+}
+
+async fn serve_listener(
+    listener: std::net::TcpListener,
+    tls_config: Option<RustlsConfig>,
+    make_service: MakeConnectionService<axum::Router>,
+    shutdown: CancellationToken,
+    grace_period: Duration,
+) -> Result<()> {
+    let local_addr = listener.local_addr()?;
+    // connections still open when the grace period ends are closed
+    let handle = axum_server::Handle::new();
+    let shutdown_task = tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.cancelled().await;
+            handle.graceful_shutdown(Some(grace_period));
+        }
+    });
+
+    let result = match tls_config {
+        Some(tls_config) => {
+            axum_server::from_tcp_rustls(listener, tls_config)
+                .map(ClientCertAcceptor)
+                .handle(handle)
+                .serve(make_service)
+                .await
+        }
+        None => {
+            axum_server::from_tcp(listener)
+                .handle(handle)
+                .serve(make_service)
+                .await
+        }
+    }
+    .with_context(|| format!("dav server error on {}", local_addr));
+    shutdown_task.abort();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    /// A fresh directory for a test, removed when it goes out of scope.
+    struct TempDir(std::path::PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = std::path::Path;
+
+        fn deref(&self) -> &std::path::Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<std::path::Path> for TempDir {
+        fn as_ref(&self) -> &std::path::Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("dav-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    /// The router of `config` with a state of its own.
+    fn test_router(config: &super::DavConfig) -> axum::Router {
+        super::build_router(config, &super::ServerState::default())
+    }
+
+    /// A request of `method` for `uri`, logged in as `user` with `password`
+    /// over Basic auth.
+    fn basic_auth_request(
+        method: &str,
+        uri: &str,
+        user: &str,
+        password: &str,
+    ) -> axum::http::request::Builder {
+        use base64::Engine;
+
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(
+                axum::http::header::AUTHORIZATION,
+                format!("Basic {}", credentials),
+            )
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn test_dav_server() {
+        use super::*;
+        println!("pwd: {:?}", std::env::current_dir().unwrap());
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind("127.0.0.1:8080".parse().unwrap())
+            .build();
+        server.run().await.unwrap();
+    }
+
+    #[ignore]
     #[tokio::test]
     async fn test_dav_server_tls_pem() {
         test_dav_server_tls(TlsConfigType::Pem).await;
@@ -290,6 +1776,1922 @@ mod tests {
         test_dav_server_tls(TlsConfigType::Der).await;
     }
 
+    #[tokio::test]
+    async fn test_dav_server_graceful_shutdown() {
+        use super::*;
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(100))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        assert_ne!(bound.local_addrs()[0].port(), 0);
+        let task = tokio::spawn(bound.serve());
+
+        handle.shutdown_and_wait().await;
+        assert!(handle.is_stopped());
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_shutdown_cuts_off_slow_requests() {
+        use super::*;
+        use std::io::{Read, Write};
+
+        let root = temp_dir("slow");
+        let server = DavServer::builder()
+            .root(root.to_str().unwrap().to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(100))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+
+        // an upload that never finishes
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"PUT /slow.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000\r\n\r\npart",
+            )
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+        let read = tokio::task::spawn_blocking(move || {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream.read_to_end(&mut Vec::new())
+        })
+        .await
+        .unwrap();
+        assert!(
+            !matches!(&read, Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)),
+            "connection still open after the grace period"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_bind_error() {
+        use super::*;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind(listener.local_addr().unwrap())
+            .build();
+        let handle = server.shutdown_handle();
+
+        assert!(server.bind().await.is_err());
+        assert!(handle.is_stopped());
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_multiple_listeners() {
+        use super::*;
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .listeners(vec![
+                Listener::new("127.0.0.1:0".parse().unwrap()),
+                Listener::new("127.0.0.1:0".parse().unwrap()),
+            ])
+            .build();
+        let bound = server.bind().await.unwrap();
+        let addrs = bound.local_addrs();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0], addrs[1]);
+    }
+
+    #[test]
+    fn test_listener_tls_requires_cert() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .add_listener(Listener::new("127.0.0.1:0".parse().unwrap()).tls(true));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_dav_server_reload() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap());
+        let server = config.clone().build();
+
+        let status = server
+            .reload(
+                config
+                    .clone()
+                    .authorization("user".to_string(), "pass".to_string()),
+            )
+            .unwrap();
+        assert_eq!(status, ReloadStatus::Applied);
+
+        let status = server
+            .reload(config.clone().bind("127.0.0.1:1".parse().unwrap()))
+            .unwrap();
+        assert_eq!(status, ReloadStatus::RebindRequired);
+
+        assert!(server
+            .reload(config.clone().http_path("no-slash".to_string()))
+            .is_err());
+
+        // a file that fails to load keeps the others from being switched
+        let dir = temp_dir("reload-files");
+        std::fs::write(dir.join("share_uses.json"), "broken").unwrap();
+        assert!(server
+            .reload(
+                config
+                    .lock_file(dir.join("locks.json"))
+                    .share_use_file(dir.join("share_uses.json"))
+            )
+            .is_err());
+        assert!(!dir.join("locks.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_reload_swaps_router() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap());
+        let server = config.clone().build();
+        let bound = server.bind().await.unwrap();
+        let options = || {
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let resp = bound.listeners[0]
+            .router
+            .clone()
+            .oneshot(options())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        server
+            .reload(config.authorization("user".to_string(), "pass".to_string()))
+            .unwrap();
+        let resp = bound.listeners[0]
+            .router
+            .clone()
+            .oneshot(options())
+            .await
+            .unwrap();
+        assert_ne!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_accounts_have_own_roots() {
+        use super::*;
+
+        let dir = temp_dir("accounts");
+        for user in ["alice", "bob"] {
+            std::fs::create_dir_all(dir.join(user)).unwrap();
+            std::fs::write(dir.join(user).join(format!("{}.txt", user)), user).unwrap();
+        }
+        let root = |user: &str| dir.join(user).to_str().unwrap().to_string();
+        let config = DavConfig::new()
+            .add_account(Account::new("alice".to_string(), "a".to_string()).root(root("alice")))
+            .add_account(Account::new("bob".to_string(), "b".to_string()).root(root("bob")));
+        config.validate().unwrap();
+        let router = test_router(&config);
+
+        let get = |path: &str, user: &str, password: &str| {
+            let req = basic_auth_request("GET", path, user, password)
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(get("/alice.txt", "alice", "a").await, StatusCode::OK);
+        assert_eq!(get("/bob.txt", "alice", "a").await, StatusCode::NOT_FOUND);
+        assert_eq!(get("/bob.txt", "bob", "b").await, StatusCode::OK);
+        assert_eq!(get("/bob.txt", "bob", "a").await, StatusCode::UNAUTHORIZED);
+
+        // an account without a root needs the default one
+        assert!(config
+            .add_account(Account::new("carol".to_string(), "c".to_string()))
+            .validate()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_permissions() {
+        use super::*;
+
+        let dir = temp_dir("permissions");
+        std::fs::write(dir.join("shared.txt"), "shared").unwrap();
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .add_account(
+                Account::new("reader".to_string(), "r".to_string())
+                    .permission(Permission::ReadOnly),
+            )
+            .add_account(
+                Account::new("uploader".to_string(), "u".to_string())
+                    .permission(Permission::UploadOnly),
+            )
+            .add_account(Account::new("writer".to_string(), "w".to_string()));
+        let router = test_router(&config);
+
+        let request = |method: &str, path: &str, user: &str| {
+            let req = basic_auth_request(method, path, user, &user[..1])
+                .body(match method {
+                    "PUT" => Body::from("data"),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(
+            request("GET", "/shared.txt", "reader").await,
+            StatusCode::OK
+        );
+        for method in ["PUT", "DELETE", "MKCOL", "PROPPATCH", "LOCK"] {
+            assert_eq!(
+                request(method, "/new.txt", "reader").await,
+                StatusCode::FORBIDDEN
+            );
+        }
+
+        assert_eq!(
+            request("PUT", "/new.txt", "uploader").await,
+            StatusCode::CREATED
+        );
+        // uploading never overwrites, whatever the conditions of the request
+        for (name, value) in [("If-Match", "*"), ("If-None-Match", "\"x\"")] {
+            let req = basic_auth_request("PUT", "/shared.txt", "uploader", "u")
+                .header(name, value)
+                .body(Body::from("data"))
+                .unwrap();
+            let status = router.clone().oneshot(req).await.unwrap().status();
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", name);
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("shared.txt")).unwrap(),
+            "shared"
+        );
+        assert_eq!(
+            request("PUT", "/new.txt", "uploader").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            request("DELETE", "/new.txt", "uploader").await,
+            StatusCode::FORBIDDEN
+        );
+
+        assert_eq!(
+            request("PUT", "/new.txt", "writer").await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            request("DELETE", "/new.txt", "writer").await,
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_read() {
+        use super::*;
+
+        let dir = temp_dir("anonymous");
+        std::fs::write(dir.join("public.txt"), "public").unwrap();
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .authorization("alice".to_string(), "a".to_string())
+            .anonymous_read(true);
+        let router = test_router(&config);
+
+        let request = |method: &str, credentials: Option<(&str, &str)>| {
+            let req = match credentials {
+                Some((user, password)) => basic_auth_request(method, "/public.txt", user, password),
+                None => Request::builder().method(method).uri("/public.txt"),
+            };
+            let req = req
+                .header("Depth", "0")
+                .body(match method {
+                    "PUT" => Body::from("changed"),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(request("GET", None).await, StatusCode::OK);
+        assert_eq!(request("PROPFIND", None).await, StatusCode::MULTI_STATUS);
+        assert_eq!(request("OPTIONS", None).await, StatusCode::OK);
+        for method in ["PUT", "DELETE", "PROPPATCH", "LOCK"] {
+            assert_eq!(request(method, None).await, StatusCode::UNAUTHORIZED);
+        }
+        // wrong credentials are not waved through as anonymous
+        assert_eq!(
+            request("GET", Some(("alice", "wrong"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            request("PUT", Some(("alice", "a"))).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public.txt")).unwrap(),
+            "changed"
+        );
+
+        assert!(DavConfig::new().anonymous_read(true).validate().is_err());
+
+        // without accounts nobody gets to write
+        let anonymous_only = [
+            (
+                DavConfig::new()
+                    .root(dir.to_str().unwrap().to_string())
+                    .anonymous_read(true),
+                "/public.txt",
+            ),
+            (
+                DavConfig::new().add_mount(
+                    Mount::new("/public/".to_string(), dir.to_str().unwrap().to_string())
+                        .access(MountAccess::AnonymousRead),
+                ),
+                "/public/public.txt",
+            ),
+        ];
+        for (config, uri) in anonymous_only {
+            config.validate().unwrap();
+            let router = test_router(&config);
+            for (method, expected) in [("GET", StatusCode::OK), ("PUT", StatusCode::FORBIDDEN)] {
+                let req = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(match method {
+                        "PUT" => Body::from("anyone"),
+                        _ => Body::empty(),
+                    })
+                    .unwrap();
+                let status = router.clone().oneshot(req).await.unwrap().status();
+                assert_eq!(status, expected, "{} {}", method, uri);
+            }
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public.txt")).unwrap(),
+            "changed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        use super::*;
+
+        let dir = temp_dir("read-only");
+        std::fs::write(dir.join("published.txt"), "published").unwrap();
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .read_only(true);
+        let router = test_router(&config);
+
+        let request = |method: &str, path: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri(path)
+                .header("Depth", "0")
+                .body(match method {
+                    "PUT" => Body::from("changed"),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+        assert_eq!(
+            request("GET", "/published.txt").await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            request("PROPFIND", "/published.txt").await.status(),
+            StatusCode::MULTI_STATUS
+        );
+        for method in [
+            "PUT",
+            "DELETE",
+            "MKCOL",
+            "PROPPATCH",
+            "LOCK",
+            "MOVE",
+            "COPY",
+        ] {
+            assert_eq!(
+                request(method, "/published.txt").await.status(),
+                StatusCode::FORBIDDEN
+            );
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("published.txt")).unwrap(),
+            "published"
+        );
+
+        let options = request("OPTIONS", "/published.txt").await;
+        let allow = options.headers()[header::ALLOW].to_str().unwrap();
+        assert_eq!(allow, "HEAD,GET,OPTIONS,PROPFIND");
+    }
+
+    #[tokio::test]
+    async fn test_mounts() {
+        use super::*;
+        use axum::body::HttpBody;
+
+        let dir = temp_dir("mounts");
+        let (projects, media) = (dir.join("projects"), dir.join("media"));
+        std::fs::create_dir_all(&projects).unwrap();
+        std::fs::create_dir_all(&media).unwrap();
+        std::fs::write(projects.join("plan.txt"), "plan").unwrap();
+        std::fs::write(media.join("song.txt"), "song").unwrap();
+        let config = DavConfig::new()
+            .authorization("alice".to_string(), "a".to_string())
+            .add_mount(Mount::new(
+                "/projects/".to_string(),
+                projects.to_str().unwrap().to_string(),
+            ))
+            .add_mount(
+                Mount::new("/media/".to_string(), media.to_str().unwrap().to_string())
+                    .read_only(true)
+                    .access(MountAccess::Public),
+            );
+        config.validate().unwrap();
+        let router = test_router(&config);
+
+        let request = |method: &str, path: &str, login: bool| {
+            let req = match login {
+                true => basic_auth_request(method, path, "alice", "a"),
+                false => Request::builder().method(method).uri(path),
+            };
+            let req = req
+                .header("Depth", "1")
+                .body(match method {
+                    "PUT" => Body::from("data"),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+        let mut list = request("PROPFIND", "/", false).await;
+        assert_eq!(list.status(), StatusCode::MULTI_STATUS);
+        let body = list.body_mut().data().await.unwrap().unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("<D:href>/projects/</D:href>"), "{}", body);
+        assert!(body.contains("<D:href>/media/</D:href>"), "{}", body);
+
+        let status =
+            |method, path, login| async move { request(method, path, login).await.status() };
+        assert_eq!(
+            status("GET", "/media/song.txt", false).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("PUT", "/media/new.txt", true).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("GET", "/projects/plan.txt", false).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status("GET", "/projects/plan.txt", true).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("PUT", "/projects/new.txt", true).await,
+            StatusCode::CREATED
+        );
+
+        let mount = |path: &str| Mount::new(path.to_string(), media.to_str().unwrap().to_string());
+        for invalid in ["/a/b/", "media", "/../", "/"] {
+            assert!(
+                DavConfig::new()
+                    .add_mount(mount(invalid))
+                    .validate()
+                    .is_err(),
+                "{}",
+                invalid
+            );
+        }
+        // the root is served at / as well
+        assert!(DavConfig::new()
+            .root(projects.to_str().unwrap().to_string())
+            .add_mount(mount("/media/"))
+            .validate()
+            .is_err());
+        DavConfig::new()
+            .root(projects.to_str().unwrap().to_string())
+            .http_path("/dav/".to_string())
+            .add_mount(mount("/media/"))
+            .validate()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_locks() {
+        use super::*;
+
+        let dir = temp_dir("locks");
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("doc.txt"), "draft").unwrap();
+        let config = DavConfig::new()
+            .root(root.to_str().unwrap().to_string())
+            .add_account(Account::new("alice".to_string(), "a".to_string()))
+            .add_account(Account::new("bob".to_string(), "b".to_string()));
+        let lock_file = dir.join("locks.json");
+        let router = |locks: &Arc<Locks>| {
+            build_router(
+                &config,
+                &ServerState {
+                    locks: locks.clone(),
+                    ..Default::default()
+                },
+            )
+        };
+        let request =
+            |router: &axum::Router, method: &str, user: &str, headers: &[(&str, &str)]| {
+                let mut req = basic_auth_request(method, "/doc.txt", user, &user[..1]);
+                for (name, value) in headers {
+                    req = req.header(*name, *value);
+                }
+                let req = req
+                    .body(match method {
+                        "PUT" => Body::from("edited"),
+                        "LOCK" => Body::from(
+                            r#"<?xml version="1.0" encoding="utf-8"?>
+                        <D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope>
+                        <D:locktype><D:write/></D:locktype><D:owner>alice</D:owner></D:lockinfo>"#,
+                        ),
+                        _ => Body::empty(),
+                    })
+                    .unwrap();
+                router.clone().oneshot(req)
+            };
+
+        let locks = Arc::new(Locks::default());
+        locks.switch(Locks::load(Some(&lock_file)).unwrap());
+        let first = router(&locks);
+        let lock = request(&first, "LOCK", "alice", &[]).await.unwrap();
+        assert_eq!(lock.status(), StatusCode::OK);
+        let token = lock.headers()["Lock-Token"].to_str().unwrap().to_string();
+        let held = format!("({})", token);
+
+        assert_eq!(
+            request(&first, "LOCK", "bob", &[]).await.unwrap().status(),
+            StatusCode::LOCKED
+        );
+        // the token does not help anyone but the owner
+        assert_eq!(
+            request(&first, "PUT", "alice", &[]).await.unwrap().status(),
+            StatusCode::LOCKED
+        );
+        assert_eq!(
+            request(&first, "PUT", "bob", &[("If", &held)])
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::LOCKED
+        );
+        assert_eq!(
+            request(&first, "PUT", "alice", &[("If", &held)])
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::NO_CONTENT
+        );
+
+        // the lock survives a restart
+        locks.flush();
+        let restarted = Arc::new(Locks::default());
+        restarted.switch(Locks::load(Some(&lock_file)).unwrap());
+        let second = router(&restarted);
+        assert_eq!(
+            request(&second, "DELETE", "bob", &[])
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::LOCKED
+        );
+        // a new lock file takes over the locks held
+        let moved_file = dir.join("moved.json");
+        std::fs::write(&moved_file, "[]").unwrap();
+        restarted.switch(Locks::load(Some(&moved_file)).unwrap());
+        restarted.flush();
+        let moved = std::fs::read_to_string(&moved_file).unwrap();
+        assert!(moved.contains(token.trim_matches(['<', '>'])), "{}", moved);
+        assert_eq!(
+            request(&second, "UNLOCK", "alice", &[("Lock-Token", &token)])
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            request(&second, "PUT", "bob", &[]).await.unwrap().status(),
+            StatusCode::NO_CONTENT
+        );
+
+        // an expired lock is no longer enforced
+        let lock = request(&second, "LOCK", "alice", &[("Timeout", "Second-0")])
+            .await
+            .unwrap();
+        assert_eq!(lock.status(), StatusCode::OK);
+        assert_eq!(
+            request(&second, "PUT", "bob", &[]).await.unwrap().status(),
+            StatusCode::NO_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_locks_across_roots() {
+        use super::*;
+
+        let dir = temp_dir("locks-roots");
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(dir.join("docs").join("doc.txt"), "draft").unwrap();
+        std::fs::write(dir.join("docs").join("notes.txt"), "draft").unwrap();
+        // the folder is served both below the root and as a mount
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .http_path("/dav/".to_string())
+            .add_account(Account::new("alice".to_string(), "a".to_string()))
+            .add_account(Account::new("bob".to_string(), "b".to_string()))
+            .add_mount(Mount::new(
+                "/docs/".to_string(),
+                dir.join("docs").to_str().unwrap().to_string(),
+            ));
+        config.validate().unwrap();
+        let router = test_router(&config);
+        let request = |method: &str, uri: &str, user: &str| {
+            let req = basic_auth_request(method, uri, user, &user[..1])
+                .body(match method {
+                    "PUT" => Body::from("edited"),
+                    "LOCK" => Body::from(
+                        r#"<?xml version="1.0" encoding="utf-8"?>
+                    <D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope>
+                    <D:locktype><D:write/></D:locktype></D:lockinfo>"#,
+                    ),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            router.clone().oneshot(req)
+        };
+
+        let lock = request("LOCK", "/dav/docs/doc.txt", "alice").await.unwrap();
+        assert_eq!(lock.status(), StatusCode::OK);
+        assert_eq!(
+            request("PUT", "/docs/doc.txt", "bob")
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::LOCKED
+        );
+        let lock = request("LOCK", "/docs/notes.txt", "alice").await.unwrap();
+        assert_eq!(lock.status(), StatusCode::OK);
+        assert_eq!(
+            request("PUT", "/dav/docs/notes.txt", "bob")
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::LOCKED
+        );
+        // the folder holding a locked file cannot be deleted either
+        assert_eq!(
+            request("DELETE", "/dav/docs/", "bob")
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::LOCKED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dead_properties() {
+        use super::*;
+
+        let dir = temp_dir("props");
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("doc.txt"), "draft").unwrap();
+        let config = DavConfig::new().root(root.to_str().unwrap().to_string());
+        let property_file = dir.join("properties.redb");
+        let router = |properties: &Arc<Properties>| {
+            build_router(
+                &config,
+                &ServerState {
+                    properties: properties.clone(),
+                    ..Default::default()
+                },
+            )
+        };
+        let request = |router: &axum::Router,
+                       method: &str,
+                       uri: &str,
+                       headers: &[(&str, &str)],
+                       body: &'static str| {
+            let mut req = Request::builder().method(method).uri(uri);
+            for (name, value) in headers {
+                req = req.header(*name, *value);
+            }
+            let req = req.body(Body::from(body)).unwrap();
+            let router = router.clone();
+            async move {
+                let res = router.oneshot(req).await.unwrap();
+                let status = res.status();
+                let mut body = res.into_body();
+                let mut content = Vec::new();
+                while let Some(chunk) = axum::body::HttpBody::data(&mut body).await {
+                    content.extend_from_slice(&chunk.unwrap());
+                }
+                (status, String::from_utf8(content).unwrap())
+            }
+        };
+        const SET: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:example"><D:set><D:prop>
+            <Z:tags>red</Z:tags></D:prop></D:set></D:propertyupdate>"#;
+        const GET: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:propfind xmlns:D="DAV:" xmlns:Z="urn:example"><D:prop><Z:tags/></D:prop></D:propfind>"#;
+        const ALL: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+            <D:propfind xmlns:D="DAV:"><D:allprop/></D:propfind>"#;
+        const DEPTH_0: &[(&str, &str)] = &[("Depth", "0")];
+
+        let properties = Arc::new(Properties::default());
+        properties.open(Some(&property_file)).unwrap();
+        let first = router(&properties);
+        let (status, body) = request(&first, "PROPPATCH", "/doc.txt", &[], SET).await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("200 OK"), "{}", body);
+        let (_, body) = request(&first, "PROPFIND", "/doc.txt", DEPTH_0, GET).await;
+        assert!(body.contains(">red<"), "{}", body);
+
+        // the property moves with the file
+        let (status, _) = request(
+            &first,
+            "MOVE",
+            "/doc.txt",
+            &[("Destination", "/moved.txt")],
+            "",
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, body) = request(&first, "PROPFIND", "/moved.txt", DEPTH_0, ALL).await;
+        assert!(body.contains(">red<"), "{}", body);
+
+        // a copied folder keeps its properties and those of its files
+        std::fs::create_dir_all(root.join("folder")).unwrap();
+        std::fs::write(root.join("folder").join("doc.txt"), "draft").unwrap();
+        for path in ["/folder/", "/folder/doc.txt"] {
+            let (status, _) = request(&first, "PROPPATCH", path, &[], SET).await;
+            assert_eq!(status, StatusCode::MULTI_STATUS);
+        }
+        let (status, _) =
+            request(&first, "COPY", "/folder/", &[("Destination", "/copy/")], "").await;
+        assert_eq!(status, StatusCode::CREATED);
+        for path in ["/copy/", "/copy/doc.txt"] {
+            let (_, body) = request(&first, "PROPFIND", path, DEPTH_0, GET).await;
+            assert!(body.contains(">red<"), "{}: {}", path, body);
+        }
+
+        // a folder served under another root shares its properties
+        let nested = build_router(
+            &DavConfig::new().root(root.join("copy").to_str().unwrap().to_string()),
+            &ServerState {
+                properties: properties.clone(),
+                ..Default::default()
+            },
+        );
+        let (_, body) = request(&nested, "PROPFIND", "/doc.txt", DEPTH_0, GET).await;
+        assert!(body.contains(">red<"), "{}", body);
+
+        // and survives a restart
+        properties.close().unwrap();
+        let restarted = Arc::new(Properties::default());
+        restarted.open(Some(&property_file)).unwrap();
+        let second = router(&restarted);
+        let (_, body) = request(&second, "PROPFIND", "/moved.txt", DEPTH_0, GET).await;
+        assert!(body.contains(">red<"), "{}", body);
+
+        // a new property file keeps the properties set
+        let other_file = dir.join("other.redb");
+        restarted.open(Some(&other_file)).unwrap();
+        let (_, body) = request(&second, "PROPFIND", "/moved.txt", DEPTH_0, GET).await;
+        assert!(body.contains(">red<"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_shares() {
+        use super::*;
+
+        let dir = temp_dir("shares");
+        std::fs::create_dir_all(dir.join("photos")).unwrap();
+        std::fs::write(dir.join("photos/cat.jpg"), "cat").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let key = ShareKey::generate();
+        let in_an_hour = SystemTime::now() + Duration::from_secs(3600);
+        let read = Share::new("/photos".to_string(), in_an_hour, ShareScope::Read);
+        let write = Share::new("/photos".to_string(), in_an_hour, ShareScope::Write);
+        let limited = Share::new("/photos".to_string(), in_an_hour, ShareScope::Read).max_uses(1);
+        let expired = Share::new("/photos".to_string(), SystemTime::now(), ShareScope::Read);
+        let revoked = Share::new("/photos".to_string(), in_an_hour, ShareScope::Read);
+        let escaping = Share::new("/photos/..".to_string(), in_an_hour, ShareScope::Read);
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .http_path("/dav/".to_string())
+            .authorization("alice".to_string(), "a".to_string())
+            .share_key(key.clone())
+            .revoke_share(revoked.id.clone());
+        config.validate().unwrap();
+        let use_file = dir.join("share_uses.json");
+        let share_uses = Arc::new(ShareUses::default());
+        share_uses.switch(ShareUses::load(Some(&use_file)).unwrap());
+        let router = build_router(
+            &config,
+            &ServerState {
+                share_uses: share_uses.clone(),
+                ..Default::default()
+            },
+        );
+
+        let request = |method: &str, path: String, bearer: Option<&Share>| {
+            let mut req = Request::builder().method(method).uri(path);
+            if let Some(share) = bearer {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", key.mint(share)));
+            }
+            let req = req
+                .header("Depth", "1")
+                .body(match method {
+                    "PUT" => Body::from("dog"),
+                    _ => Body::empty(),
+                })
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+        let status = |method: &str, path: &str, bearer: Option<&Share>| {
+            let response = request(method, path.to_string(), bearer);
+            async move { response.await.status() }
+        };
+
+        assert_eq!(
+            status("GET", "/dav/cat.jpg", Some(&read)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("GET", "/dav/secret.txt", Some(&read)).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status("PUT", "/dav/dog.jpg", Some(&read)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status("PUT", "/dav/dog.jpg", Some(&write)).await,
+            StatusCode::CREATED
+        );
+        assert!(dir.join("photos/dog.jpg").exists());
+
+        let link = config.share_path(&key.mint(&read));
+        let response = request("PROPFIND", link.clone(), None).await;
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        let mut body = response.into_body();
+        let mut xml = Vec::new();
+        while let Some(chunk) = axum::body::HttpBody::data(&mut body).await {
+            xml.extend_from_slice(&chunk.unwrap());
+        }
+        let body = String::from_utf8(xml).unwrap();
+        assert!(body.contains(&format!("{}cat.jpg", link)), "{}", body);
+        assert_eq!(
+            status("GET", &format!("{}cat.jpg", link), None).await,
+            StatusCode::OK
+        );
+        // logs and events do not give the link away
+        assert_eq!(
+            share::redact_token(&format!("{}cat.jpg", link)),
+            "/dav/_share/<redacted>/cat.jpg"
+        );
+
+        for share in [&expired, &revoked, &escaping] {
+            assert_eq!(
+                status("GET", "/dav/cat.jpg", Some(share)).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+        let forged = format!("{}x", key.mint(&read));
+        assert_eq!(
+            status("GET", &format!("/dav/_share/{}/cat.jpg", forged), None).await,
+            StatusCode::FORBIDDEN
+        );
+
+        // browsing does not count as a use, nor does a failed transfer
+        assert_eq!(
+            status("PROPFIND", "/dav/", Some(&limited)).await,
+            StatusCode::MULTI_STATUS
+        );
+        assert_eq!(
+            status("GET", "/dav/missing.jpg", Some(&limited)).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(share_uses.get(&limited.id), 0);
+        assert_eq!(
+            status("GET", "/dav/cat.jpg", Some(&limited)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("GET", "/dav/cat.jpg", Some(&limited)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(share_uses.get(&limited.id), 1);
+
+        // and the uses survive a restart
+        share_uses.flush();
+        let restarted = ShareUses::default();
+        restarted.switch(ShareUses::load(Some(&use_file)).unwrap());
+        assert_eq!(restarted.get(&limited.id), 1);
+    }
+
+    #[tokio::test]
+    async fn test_password_hashes() {
+        use super::*;
+
+        let bcrypt_hash = bcrypt::hash("b", 4).unwrap();
+        let dir = temp_dir("password-hashes");
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .add_account(
+                Account::from_hash("alice".to_string(), hash_password("a").unwrap()).unwrap(),
+            )
+            .add_account(Account::from_hash("bob".to_string(), bcrypt_hash).unwrap());
+        config.validate().unwrap();
+        assert!(Account::from_hash("carol".to_string(), "c".to_string()).is_err());
+        assert!(Account::from_hash("carol".to_string(), "$2b$04$short".to_string()).is_err());
+        let router = test_router(&config);
+
+        let get = |user: &str, password: &str| {
+            let req = basic_auth_request("PROPFIND", "/", user, password)
+                .header("Depth", "0")
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        // the second round is answered from the verification cache
+        for _ in 0..2 {
+            assert_eq!(get("alice", "a").await, StatusCode::MULTI_STATUS);
+            assert_eq!(get("bob", "b").await, StatusCode::MULTI_STATUS);
+            assert_eq!(get("alice", "b").await, StatusCode::UNAUTHORIZED);
+            assert_eq!(get("bob", "a").await, StatusCode::UNAUTHORIZED);
+            assert_eq!(get("carol", "a").await, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_htpasswd() {
+        use super::*;
+
+        let dir = temp_dir("htpasswd");
+        let path = dir.join("htpasswd");
+        let bcrypt_hash = bcrypt::hash_with_result("a", 4)
+            .unwrap()
+            .format_for_version(bcrypt::Version::TwoY);
+        std::fs::write(
+            &path,
+            format!(
+                "# users\nalice:{}\nbob:{{SHA}}5en6G6MezRroT3XKqkdPOmY/BfQ=\n\n\
+                 carol:$apr1$r31....$gnsoqlxyxQQ0Ot5JCwiei.\n\
+                 dave:$apr1$abcdefgh$CWmSdRXg6.q2WlUC6/oKv1\n",
+                bcrypt_hash
+            ),
+        )
+        .unwrap();
+        assert!(Account::from_hash("erin".to_string(), "$apr1$x$short".to_string()).is_err());
+        assert!(Account::from_hash("erin".to_string(), "{SHA}c2hvcnQ=".to_string()).is_err());
+
+        let server = DavServer::builder()
+            .root(dir.to_str().unwrap().to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .htpasswd(&path)
+            .htpasswd_poll_interval(Duration::from_millis(10))
+            .no_lockout()
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let task = tokio::spawn(bound.serve());
+
+        let propfind = |user: &str, password: &str| {
+            let req = basic_auth_request("PROPFIND", "/", user, password)
+                .header("Depth", "0")
+                .body(Body::empty())
+                .unwrap();
+            let router = server.router.lock().unwrap().clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        for (user, password) in [
+            ("alice", "a"),
+            ("bob", "secret"),
+            ("carol", "secret"),
+            ("dave", "a much longer password than sixteen"),
+        ] {
+            assert_eq!(propfind(user, password).await, StatusCode::MULTI_STATUS);
+            assert_eq!(propfind(user, "wrong").await, StatusCode::UNAUTHORIZED);
+        }
+
+        std::fs::write(&path, "carol:$apr1$r31....$gnsoqlxyxQQ0Ot5JCwiei.\n").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while propfind("bob", "secret").await != StatusCode::UNAUTHORIZED {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(propfind("carol", "secret").await, StatusCode::MULTI_STATUS);
+
+        // a broken file keeps the users loaded before
+        std::fs::write(&path, "carol\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(propfind("carol", "secret").await, StatusCode::MULTI_STATUS);
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ip_filter() {
+        use super::*;
+        use axum::extract::ConnectInfo;
+
+        let net = |s: &str| s.parse::<IpNet>().unwrap();
+        assert_eq!(net("192.168.1.7/24"), net("192.168.1.0/24"));
+        assert_eq!(net("::ffff:10.1.2.3/104").to_string(), "10.0.0.0/8");
+        assert!(net("10.0.0.0/8").contains("::ffff:10.9.8.7".parse().unwrap()));
+        assert!(!net("10.0.0.0/8").contains("11.0.0.1".parse().unwrap()));
+        assert!(net("0.0.0.0/0").contains("1.2.3.4".parse().unwrap()));
+        assert!(!net("0.0.0.0/0").contains("2001:db8::1".parse().unwrap()));
+        assert!(net("fe80::1").contains("fe80::1".parse().unwrap()));
+        for invalid in ["10.0.0.0/33", "fe80::/129", "10.0.0/8", "10.0.0.0/x"] {
+            assert!(invalid.parse::<IpNet>().is_err(), "{}", invalid);
+        }
+
+        let dir = temp_dir("ip-filter");
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .authorization("alice".to_string(), "a".to_string())
+            .ip_filter(IpFilter::private_lan())
+            .deny(net("192.168.1.13"));
+        let router = test_router(&config);
+
+        let propfind = |client: Option<&str>| {
+            let mut req = Request::builder()
+                .uri("/missing/")
+                .method("PROPFIND")
+                .body(Body::empty())
+                .unwrap();
+            if let Some(client) = client {
+                let ip = client.parse::<std::net::IpAddr>().unwrap();
+                req.extensions_mut()
+                    .insert(ConnectInfo(SocketAddr::new(ip, 50000)));
+            }
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        // allowed clients get as far as the login
+        for client in ["192.168.1.2", "::ffff:192.168.1.2", "127.0.0.1", "fd00::2"] {
+            assert_eq!(propfind(Some(client)).await, StatusCode::UNAUTHORIZED);
+        }
+        for client in [
+            Some("8.8.8.8"),
+            Some("2001:db8::1"),
+            Some("192.168.1.13"),
+            None,
+        ] {
+            assert_eq!(propfind(client).await, StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ip_filter_covers_every_listener() {
+        use super::*;
+        use axum::extract::ConnectInfo;
+
+        let (cert, key) = self_signed_pem();
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .tls(TlsConfig::pem(cert, key))
+            .https_redirect("127.0.0.1:0".parse().unwrap())
+            .metrics_bind("127.0.0.1:0".parse().unwrap());
+        let server = config.clone().deny("10.0.0.0/8".parse().unwrap()).build();
+        let bound = server.bind().await.unwrap();
+        let status = |listener: usize, client: &str| {
+            let mut req = Request::builder()
+                .uri("/")
+                .header(header::HOST, "example.com")
+                .body(Body::empty())
+                .unwrap();
+            let ip = client.parse::<std::net::IpAddr>().unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::new(ip, 50000)));
+            let router = bound.listeners[listener].router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+
+        // the https redirect and the metrics listener
+        for listener in [1, 2] {
+            assert_eq!(status(listener, "10.1.2.3").await, StatusCode::FORBIDDEN);
+            assert_ne!(status(listener, "192.168.1.2").await, StatusCode::FORBIDDEN);
+        }
+        // follow the filter of a reloaded config
+        server.reload(config).unwrap();
+        for listener in [1, 2] {
+            assert_ne!(status(listener, "10.1.2.3").await, StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lockout() {
+        use super::*;
+        use axum::extract::ConnectInfo;
+
+        let policy = LockoutPolicy {
+            threshold: 5,
+            ban_duration: Duration::from_secs(60),
+        };
+        let dir = temp_dir("lockout");
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .authorization("alice".to_string(), "a".to_string())
+            .lockout(policy);
+        let lockouts = Arc::new(Lockouts::default());
+        let router = build_router(
+            &config,
+            &ServerState {
+                lockouts: lockouts.clone(),
+                ..Default::default()
+            },
+        );
+
+        let client = std::net::SocketAddr::from(([192, 168, 1, 2], 50000));
+        let propfind = |password: &str| {
+            let mut req = basic_auth_request("PROPFIND", "/", "alice", password)
+                .header("Depth", "0")
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut().insert(ConnectInfo(client));
+            let router = router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+
+        for _ in 0..4 {
+            assert_eq!(propfind("b").await.status(), StatusCode::UNAUTHORIZED);
+        }
+        // backing off, even with the right password
+        let resp = propfind("a").await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(resp.headers().contains_key(header::RETRY_AFTER));
+        assert!(lockouts.bans(&policy).is_empty());
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(propfind("b").await.status(), StatusCode::UNAUTHORIZED);
+        let targets = lockouts
+            .bans(&policy)
+            .into_iter()
+            .map(|ban| (ban.target, ban.failures))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [
+                (BanTarget::Ip(client.ip()), 5),
+                (BanTarget::User("alice".to_string()), 5)
+            ]
+        );
+
+        assert!(lockouts.clear(&BanTarget::User("alice".to_string())));
+        assert_eq!(propfind("a").await.status(), StatusCode::TOO_MANY_REQUESTS);
+        lockouts.clear_all();
+        assert_eq!(propfind("a").await.status(), StatusCode::MULTI_STATUS);
+    }
+
+    #[tokio::test]
+    async fn test_authenticator() {
+        use super::*;
+        use axum::http::{request::Parts, HeaderValue};
+        use futures_util::future::BoxFuture;
+
+        let dir = temp_dir("authenticator");
+        let root = dir.to_str().unwrap().to_string();
+        let propfind = |router: &axum::Router, method: &str, token: Option<&str>| {
+            let mut req = Request::builder()
+                .uri("/")
+                .method(method)
+                .header("Depth", "0");
+            if let Some(token) = token {
+                req = req.header("X-Token", token);
+            }
+            router.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        // the built in schemes challenge requests without credentials
+        let config = DavConfig::new()
+            .root(root.clone())
+            .realm("files".to_string())
+            .authorization("user".to_string(), "password".to_string());
+        let router = test_router(&config);
+        let resp = propfind(&router, "PROPFIND", None).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"files\", charset=\"UTF-8\""
+        );
+        assert!(config.realm("\"".to_string()).validate().is_err());
+
+        struct TokenAuthenticator;
+        impl Authenticator for TokenAuthenticator {
+            fn authenticate<'a>(
+                &'a self,
+                parts: &'a Parts,
+            ) -> BoxFuture<'a, Result<Identity, Challenge>> {
+                let result = match parts.headers.get("X-Token") {
+                    Some(token) if token == "secret" => Ok(Identity {
+                        user: "bot".to_string(),
+                        root: None,
+                        permission: Permission::ReadOnly,
+                    }),
+                    _ => Err(Challenge(vec![HeaderValue::from_static("Token")])),
+                };
+                Box::pin(async move { result })
+            }
+        }
+        let config = DavConfig::new()
+            .root(root)
+            .authenticator(TokenAuthenticator);
+        config.validate().unwrap();
+        let router = test_router(&config);
+        let resp = propfind(&router, "PROPFIND", Some("wrong")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers()[header::WWW_AUTHENTICATE], "Token");
+        let resp = propfind(&router, "PROPFIND", Some("secret")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+        let resp = propfind(&router, "MKCOL", Some("secret")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_digest_auth() {
+        use super::*;
+        use sha2::{Digest, Sha256};
+
+        let dir = temp_dir("digest-auth");
+        let config = DavConfig::new()
+            .root(dir.to_str().unwrap().to_string())
+            .auth_scheme(AuthScheme::Digest)
+            .add_account(Account::new("alice".to_string(), "a".to_string()).digest("dav", "a"));
+        config.validate().unwrap();
+        assert!(config
+            .clone()
+            .add_account(Account::new("bob".to_string(), "b".to_string()))
+            .validate()
+            .is_err());
+        let state = ServerState::default();
+        let router = build_router(&config, &state);
+
+        let propfind = |auth: Option<String>| {
+            let mut req = Request::builder()
+                .uri("/")
+                .method("PROPFIND")
+                .header("Depth", "0");
+            if let Some(auth) = auth {
+                req = req.header(header::AUTHORIZATION, auth);
+            }
+            router.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+        let resp = propfind(None).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let challenges = resp
+            .headers()
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .map(|c| c.to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(challenges.len(), 2);
+        assert!(challenges[0].contains("algorithm=SHA-256"));
+        assert!(challenges[1].contains("algorithm=MD5"));
+        let param = |challenge: &str, name: &str| {
+            let start = challenge.find(&format!("{}=\"", name)).unwrap() + name.len() + 2;
+            challenge[start..].split('"').next().unwrap().to_string()
+        };
+        let nonce = param(&challenges[0], "nonce");
+        let opaque = param(&challenges[0], "opaque");
+
+        let sha256 = |data: String| format!("{:x}", Sha256::digest(data));
+        let authorization = |password: &str, nc: &str| {
+            let ha1 = sha256(format!("alice:dav:{}", password));
+            let ha2 = sha256("PROPFIND:/".to_string());
+            let response = sha256(format!("{}:{}:{}:xyz:auth:{}", ha1, nonce, nc, ha2));
+            Some(format!(
+                "Digest username=\"alice\", realm=\"dav\", nonce=\"{}\", uri=\"/\", \
+                 algorithm=SHA-256, qop=auth, nc={}, cnonce=\"xyz\", response=\"{}\", \
+                 opaque=\"{}\"",
+                nonce, nc, response, opaque
+            ))
+        };
+        let status = |resp: Response| {
+            let stale = resp
+                .headers()
+                .get(header::WWW_AUTHENTICATE)
+                .map(|c| c.to_str().unwrap().ends_with("stale=true"));
+            (resp.status(), stale)
+        };
+        let ok = (StatusCode::MULTI_STATUS, None);
+        assert_eq!(
+            status(propfind(authorization("a", "00000001")).await.unwrap()),
+            ok
+        );
+        assert_eq!(
+            status(propfind(authorization("a", "00000002")).await.unwrap()),
+            ok
+        );
+        // a replayed request only needs a fresh nonce
+        assert_eq!(
+            status(propfind(authorization("a", "00000001")).await.unwrap()),
+            (StatusCode::UNAUTHORIZED, Some(true))
+        );
+        assert_eq!(
+            status(propfind(authorization("b", "00000003")).await.unwrap()),
+            (StatusCode::UNAUTHORIZED, Some(false))
+        );
+
+        // a reload keeps the nonces valid
+        let req = Request::builder()
+            .uri("/")
+            .method("PROPFIND")
+            .header("Depth", "0")
+            .header(
+                header::AUTHORIZATION,
+                authorization("a", "00000003").unwrap(),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let resp = build_router(&config, &state).oneshot(req).await.unwrap();
+        assert_eq!(status(resp), ok);
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .authorization("user".to_string(), "hunter2".to_string())
+            .tls(TlsConfig::pem(b"cert".to_vec(), b"private".to_vec()));
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("private"));
+    }
+
+    #[test]
+    fn test_https_redirect() {
+        use super::*;
+
+        let config = DavConfig::new().http_path("/dav/".to_string());
+        let redirect = |method: &str, uri: &str, port: u16| {
+            let req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::HOST, "example.com:8080")
+                .body(())
+                .unwrap();
+            let resp = https_redirect(&config, port, &req);
+            let location = resp.headers().get(header::LOCATION).cloned();
+            (
+                resp.status(),
+                location.map(|l| l.to_str().unwrap().to_string()),
+            )
+        };
+
+        assert_eq!(
+            redirect("GET", "/dav/a/b.txt?x=1", 443),
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("https://example.com/dav/a/b.txt?x=1".to_string())
+            )
+        );
+        assert_eq!(
+            redirect("PUT", "/dav/a", 8443),
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                Some("https://example.com:8443/dav/a".to_string())
+            )
+        );
+        assert_eq!(
+            redirect("GET", "/other", 443),
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("https://example.com/dav/".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn test_https_redirect_requires_tls() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .https_redirect("127.0.0.1:0".parse().unwrap());
+        assert!(config.validate().is_err());
+    }
+
+    async fn http_request(addr: std::net::SocketAddr, method: &str, path: &str) -> String {
+        http_request_with(addr, method, path, &[]).await
+    }
+
+    async fn http_request_with(
+        addr: std::net::SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> String {
+        use std::io::{Read, Write};
+
+        let headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+            method, path, headers
+        );
+        tokio::task::spawn_blocking(move || {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_serves_files_below_http_path() {
+        use super::*;
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .http_path("/dav/".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+
+        let response = http_request(addr, "GET", "/dav/vite.svg").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        let response = http_request(addr, "GET", "/vite.svg").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_metrics() {
+        use super::*;
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .metrics_bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addrs = bound.local_addrs();
+        let task = tokio::spawn(bound.serve());
+
+        let response = http_request(addrs[0], "OPTIONS", "/").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        let response = http_request(addrs[1], "GET", "/metrics").await;
+        assert!(response.contains("dav_requests_total{method=\"OPTIONS\",status=\"200\"} 1\n"));
+        assert!(response.contains("dav_request_duration_seconds_count{method=\"OPTIONS\"} 1\n"));
+
+        let snapshot = server.metrics().snapshot();
+        assert_eq!(snapshot.auth_failures, 0);
+        assert_eq!(snapshot.active_connections, 0);
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_metrics_labels_and_auth_failures() {
+        use super::*;
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .add_account(Account::new("user".to_string(), "pass".to_string()))
+            .share_key(ShareKey::generate())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+
+        // the challenge before a login is no failure
+        let response = http_request(addr, "PROPFIND", "/").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        assert_eq!(server.metrics().snapshot().auth_failures, 0);
+        // user:wrong
+        let response = http_request_with(
+            addr,
+            "PROPFIND",
+            "/",
+            &[("Authorization", "Basic dXNlcjp3cm9uZw==")],
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        assert_eq!(server.metrics().snapshot().auth_failures, 1);
+        // nor is a forged share token
+        let mut events = server.subscribe();
+        let response = http_request_with(
+            addr,
+            "GET",
+            "/index.html",
+            &[("Authorization", "Bearer forged")],
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+        assert_eq!(server.metrics().snapshot().auth_failures, 2);
+        assert!(std::iter::from_fn(|| events.try_recv().ok())
+            .any(|event| matches!(event, events::DavEvent::AuthFailed { .. })));
+
+        http_request(addr, "BREW", "/").await;
+        let rendered = server.metrics().render();
+        assert!(rendered.contains("method=\"OTHER\""), "{}", rendered);
+        assert!(!rendered.contains("BREW"), "{}", rendered);
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_events_by_identity() {
+        use super::*;
+        use events::DavEvent;
+
+        let root = temp_dir("events-id");
+        let server = DavServer::builder()
+            .root(root.to_str().unwrap().to_string())
+            .add_account(Account::new("user".to_string(), "pass".to_string()))
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let mut events = server.subscribe();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+        let mut next_event = || {
+            let mut event = events.try_recv();
+            while let Ok(DavEvent::Connected { .. }) = event {
+                event = events.try_recv();
+            }
+            event.ok()
+        };
+
+        // the challenge before a login is no failure
+        http_request(addr, "MKCOL", "/dir").await;
+        assert_eq!(next_event(), None);
+        // user:wrong
+        http_request_with(
+            addr,
+            "MKCOL",
+            "/dir",
+            &[("Authorization", "Basic dXNlcjp3cm9uZw==")],
+        )
+        .await;
+        assert!(matches!(
+            next_event(),
+            Some(DavEvent::AuthFailed { user: Some(user), .. }) if user == "user"
+        ));
+        // user:pass
+        let response = http_request_with(
+            addr,
+            "MKCOL",
+            "/dir",
+            &[("Authorization", "Basic dXNlcjpwYXNz")],
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+        assert_eq!(
+            next_event(),
+            Some(DavEvent::CollectionCreated {
+                user: Some("user".to_string()),
+                path: "/dir".to_string()
+            })
+        );
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_events() {
+        use super::*;
+        use events::DavEvent;
+
+        let root = temp_dir("events");
+        let server = DavServer::builder()
+            .root(root.to_str().unwrap().to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let mut events = server.subscribe();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+
+        let response = http_request(addr, "MKCOL", "/dir").await;
+        assert!(response.starts_with("HTTP/1.1 201"));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            DavEvent::Connected { .. }
+        ));
+        assert_eq!(
+            events.recv().await.unwrap(),
+            DavEvent::CollectionCreated {
+                user: None,
+                path: "/dir".to_string()
+            }
+        );
+
+        // failed requests publish nothing
+        http_request(addr, "MKCOL", "/dir").await;
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            DavEvent::Connected { .. }
+        ));
+        assert!(events.try_recv().is_err());
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_metrics_path_validation() {
+        use super::*;
+
+        let config = DavConfig::new()
+            .root("../public".to_string())
+            .http_path("/dav/".to_string());
+        assert!(config
+            .clone()
+            .metrics_path("/metrics".to_string())
+            .validate()
+            .is_ok());
+        assert!(config
+            .clone()
+            .metrics_path("/dav/metrics".to_string())
+            .validate()
+            .is_ok());
+        assert!(config
+            .clone()
+            .metrics_path("/dav/".to_string())
+            .validate()
+            .is_err());
+        assert!(config
+            .metrics_path("metrics".to_string())
+            .validate()
+            .is_err());
+    }
+
+    fn self_signed_pem() -> (Vec<u8>, Vec<u8>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        (
+            cert.serialize_pem().unwrap().into_bytes(),
+            cert.serialize_private_key_pem().into_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_reload_tls() {
+        use super::*;
+
+        let (cert, key) = self_signed_pem();
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .tls(TlsConfig::pem(cert, key))
+            .build();
+        let _bound = server.bind().await.unwrap();
+        let rustls_config = server.rustls_config.lock().unwrap().clone().unwrap();
+        let old = rustls_config.get_inner();
+
+        assert!(server
+            .reload_tls(TlsConfig::pem(b"bad".to_vec(), b"bad".to_vec()))
+            .await
+            .is_err());
+        assert!(Arc::ptr_eq(&old, &rustls_config.get_inner()));
+
+        let (cert, key) = self_signed_pem();
+        server.reload_tls(TlsConfig::pem(cert, key)).await.unwrap();
+        assert!(!Arc::ptr_eq(&old, &rustls_config.get_inner()));
+    }
+
+    #[tokio::test]
+    async fn test_client_certificates() {
+        use super::*;
+        use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, SanType};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let ca = |name: &str| {
+            let mut params = CertificateParams::new(Vec::new());
+            params.distinguished_name.push(DnType::CommonName, name);
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            rcgen::Certificate::from_params(params).unwrap()
+        };
+        let client = |ca: &rcgen::Certificate, cn: &str, sans: Vec<SanType>| {
+            let mut params = CertificateParams::new(Vec::new());
+            params.distinguished_name.push(DnType::CommonName, cn);
+            params.subject_alt_names = sans;
+            let cert = rcgen::Certificate::from_params(params).unwrap();
+            (
+                cert.serialize_der_with_signer(ca).unwrap(),
+                cert.serialize_private_key_der(),
+            )
+        };
+        let client_ca = ca("clients");
+        let alice = client(&client_ca, "alice-laptop", Vec::new());
+        let bob = client(
+            &client_ca,
+            "bob",
+            vec![SanType::Rfc822Name("bob@example.com".to_string())],
+        );
+        let mallory = client(&ca("other"), "alice-laptop", Vec::new());
+        let server_cert =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let serve = |require: bool| {
+            let tls_config = TlsConfig::der(
+                vec![server_cert.serialize_der().unwrap()],
+                server_cert.serialize_private_key_der(),
+            )
+            .client_ca_pem(client_ca.serialize_pem().unwrap().into_bytes())
+            .require_client_cert(require);
+            DavServer::builder()
+                .root("../public".to_string())
+                .bind("127.0.0.1:0".parse().unwrap())
+                .tls(tls_config)
+                .add_account(
+                    Account::new("alice".to_string(), "a".to_string())
+                        .client_cert("alice-laptop".to_string()),
+                )
+                .add_account(Account::client_cert_only(
+                    "bob".to_string(),
+                    "bob@example.com".to_string(),
+                ))
+                .shutdown_grace_period(Duration::from_millis(10))
+                .build()
+        };
+        let propfind = |addr: SocketAddr, cert: Option<&(Vec<u8>, Vec<u8>)>| {
+            let mut roots = rustls::RootCertStore::empty();
+            roots
+                .add(&rustls::Certificate(server_cert.serialize_der().unwrap()))
+                .unwrap();
+            let builder = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots);
+            let client_config = match cert {
+                Some((cert, key)) => builder
+                    .with_client_auth_cert(
+                        vec![rustls::Certificate(cert.clone())],
+                        rustls::PrivateKey(key.clone()),
+                    )
+                    .unwrap(),
+                None => builder.with_no_client_auth(),
+            };
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+            async move {
+                let stream = tokio::net::TcpStream::connect(addr).await?;
+                let mut stream = connector
+                    .connect("localhost".try_into().unwrap(), stream)
+                    .await?;
+                stream
+                    .write_all(
+                        b"PROPFIND / HTTP/1.1\r\nHost: localhost\r\nDepth: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .await?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await?;
+                Ok::<_, std::io::Error>(response)
+            }
+        };
+
+        let server = serve(false);
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+        for cert in [&alice, &bob] {
+            let response = propfind(addr, Some(cert)).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 207"), "{}", response);
+        }
+        let response = propfind(addr, None).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        assert!(propfind(addr, Some(&mallory)).await.is_err());
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+
+        let server = serve(true);
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let addr = bound.local_addrs()[0];
+        let task = tokio::spawn(bound.serve());
+        let response = propfind(addr, Some(&alice)).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 207"), "{}", response);
+        assert!(propfind(addr, None).await.is_err());
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+
+        let no_ca = DavConfig::new()
+            .root("../public".to_string())
+            .tls(TlsConfig::pem(b"cert".to_vec(), b"key".to_vec()))
+            .add_account(Account::client_cert_only(
+                "bob".to_string(),
+                "bob@example.com".to_string(),
+            ));
+        assert!(no_ca.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dav_server_watch_tls_files() {
+        use super::*;
+
+        let dir = temp_dir("watch-tls");
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let (cert, key) = self_signed_pem();
+        std::fs::write(&cert_path, cert).unwrap();
+        std::fs::write(&key_path, key).unwrap();
+
+        let server = DavServer::builder()
+            .root("../public".to_string())
+            .bind("127.0.0.1:0".parse().unwrap())
+            .tls(TlsConfig::pem_file(&cert_path, &key_path).watch(Duration::from_millis(10)))
+            .shutdown_grace_period(Duration::from_millis(10))
+            .build();
+        let handle = server.shutdown_handle();
+        let bound = server.bind().await.unwrap();
+        let rustls_config = server.rustls_config.lock().unwrap().clone().unwrap();
+        let old = rustls_config.get_inner();
+        let task = tokio::spawn(bound.serve());
+
+        // a broken cert is ignored
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::write(&cert_path, b"bad").unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(Arc::ptr_eq(&old, &rustls_config.get_inner()));
+
+        let (cert, key) = self_signed_pem();
+        std::fs::write(&key_path, key).unwrap();
+        std::fs::write(&cert_path, cert).unwrap();
+        let reloaded = async {
+            while Arc::ptr_eq(&old, &rustls_config.get_inner()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), reloaded)
+            .await
+            .unwrap();
+
+        handle.shutdown_and_wait().await;
+        task.await.unwrap().unwrap();
+    }
+
     enum TlsConfigType {
         Pem,
         Der,
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Failed logins allowed before clients have to wait between attempts.
+const FREE_FAILURES: u32 = 3;
+
+/// Wait after the first failure beyond [`FREE_FAILURES`]; it doubles with
+/// every further failure.
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// How failed logins are throttled, see [`DavConfig::lockout`](crate::DavConfig::lockout).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LockoutPolicy {
+    /// Failures of an address or user name after which it is banned.
+    pub threshold: u32,
+    /// How long a ban lasts. Failures are also forgotten after this long
+    /// without another one.
+    pub ban_duration: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        LockoutPolicy {
+            threshold: 10,
+            ban_duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// What failed logins are counted for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BanTarget {
+    Ip(IpAddr),
+    User(String),
+}
+
+/// An address or user name that may not log in for now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub failures: u32,
+    pub until: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    blocked_until: Instant,
+}
+
+/// Failed logins per address and user name of a
+/// [`DavServer`](crate::DavServer). They are kept across reloads.
+#[derive(Debug, Default)]
+pub struct Lockouts {
+    failures: Mutex<HashMap<BanTarget, Failures>>,
+}
+
+impl Lockouts {
+    /// The addresses and user names banned after too many failures.
+    pub fn bans(&self, policy: &LockoutPolicy) -> Vec<Ban> {
+        let now = Instant::now();
+        let mut bans = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, f)| f.count >= policy.threshold && f.blocked_until > now)
+            .map(|(target, f)| Ban {
+                target: target.clone(),
+                failures: f.count,
+                until: SystemTime::now() + (f.blocked_until - now),
+            })
+            .collect::<Vec<_>>();
+        bans.sort_by(|a, b| a.target.cmp(&b.target));
+        bans
+    }
+
+    /// Forget the failures of `target`, lifting its ban. Returns whether
+    /// there were any.
+    pub fn clear(&self, target: &BanTarget) -> bool {
+        self.failures.lock().unwrap().remove(target).is_some()
+    }
+
+    pub fn clear_all(&self) {
+        self.failures.lock().unwrap().clear();
+    }
+
+    /// How long the longest blocked of `targets` still has to wait.
+    pub(crate) fn blocked(&self, targets: &[BanTarget]) -> Option<Duration> {
+        let now = Instant::now();
+        let failures = self.failures.lock().unwrap();
+        targets
+            .iter()
+            .filter_map(|target| failures.get(target))
+            .map(|f| f.blocked_until.saturating_duration_since(now))
+            .filter(|wait| !wait.is_zero())
+            .max()
+    }
+
+    pub(crate) fn failed(&self, targets: &[BanTarget], policy: &LockoutPolicy) {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, f| now.duration_since(f.last) < policy.ban_duration);
+
+        for target in targets {
+            let f = failures.entry(target.clone()).or_insert(Failures {
+                count: 0,
+                last: now,
+                blocked_until: now,
+            });
+            f.count += 1;
+            f.last = now;
+            let wait = if f.count >= policy.threshold {
+                policy.ban_duration
+            } else if f.count > FREE_FAILURES {
+                let doublings = (f.count - FREE_FAILURES - 1).min(16);
+                (BACKOFF_BASE * 2u32.pow(doublings)).min(policy.ban_duration)
+            } else {
+                Duration::ZERO
+            };
+            f.blocked_until = now + wait;
+            if f.count == policy.threshold {
+                tracing::warn!(?target, "banned after {} failed logins", f.count);
+            }
+        }
+    }
+
+    pub(crate) fn succeeded(&self, targets: &[BanTarget]) {
+        let mut failures = self.failures.lock().unwrap();
+        for target in targets {
+            failures.remove(target);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use dav_server::{
+    davpath::DavPath,
+    ls::{DavLock, DavLockSystem},
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use xmltree::Element;
+
+use crate::{json_file::JsonFile, utils::canonical_root};
+
+/// Longest a lock is held without being refreshed. Clients asking for an
+/// infinite timeout get this one, so a crashed client cannot keep a file
+/// locked forever.
+pub(crate) const MAX_LOCK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Characters escaped in the segments of lock URLs.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredLock {
+    token: String,
+    /// The locked file as an absolute path with the symlinks of the folder
+    /// it was locked in resolved, so the locks of a file hold in every
+    /// folder serving it.
+    path: PathBuf,
+    /// Whether the lock was taken on a collection URL.
+    #[serde(default)]
+    collection: bool,
+    principal: Option<String>,
+    /// The `owner` element the client sent, as XML.
+    owner: Option<String>,
+    timeout: Duration,
+    expires: SystemTime,
+    shared: bool,
+    deep: bool,
+}
+
+impl StoredLock {
+    /// The lock as seen by the handler serving `root` at `prefix`. Locks
+    /// taken above `root` show up at its top.
+    fn to_dav_lock(&self, root: &Path, prefix: &str) -> DavLock {
+        let mut url = String::from(prefix.trim_end_matches('/'));
+        for component in self
+            .path
+            .strip_prefix(root)
+            .into_iter()
+            .flat_map(Path::components)
+        {
+            if let Component::Normal(segment) = component {
+                url.push('/');
+                url.extend(utf8_percent_encode(&segment.to_string_lossy(), SEGMENT));
+            }
+        }
+        if self.collection || url.len() <= prefix.trim_end_matches('/').len() {
+            url.push('/');
+        }
+        let mut path = DavPath::new(&url).unwrap_or_else(|_| DavPath::new("/").unwrap());
+        path.set_prefix(prefix).ok();
+        DavLock {
+            token: self.token.clone(),
+            path,
+            principal: self.principal.clone(),
+            owner: self
+                .owner
+                .as_ref()
+                .and_then(|owner| Element::parse(owner.as_bytes()).ok()),
+            timeout_at: Some(self.expires),
+            timeout: Some(self.timeout),
+            shared: self.shared,
+            deep: self.deep,
+        }
+    }
+
+    /// Whether the lock applies to `path`.
+    fn covers(&self, path: &Path) -> bool {
+        self.path == path || (self.deep && is_below(path, &self.path))
+    }
+}
+
+fn is_below(path: &Path, ancestor: &Path) -> bool {
+    path != ancestor && path.starts_with(ancestor)
+}
+
+/// The WebDAV locks of every folder the server serves. The routers share
+/// them, so they survive a [`DavServer::reload`](crate::DavServer::reload);
+/// with a [`DavConfig::lock_file`](crate::DavConfig::lock_file) they
+/// survive a restart as well.
+#[derive(Debug, Default)]
+pub(crate) struct Locks {
+    locks: Mutex<Vec<StoredLock>>,
+    file: JsonFile,
+}
+
+/// A lock file read by [`Locks::load`], to be switched to.
+#[derive(Debug)]
+pub(crate) struct LockFile {
+    path: Option<PathBuf>,
+    locks: Vec<StoredLock>,
+}
+
+impl Locks {
+    /// Read `file` for [`Locks::switch`].
+    pub(crate) fn load(file: Option<&Path>) -> Result<LockFile> {
+        let stored: Option<Vec<StoredLock>> = file.map(JsonFile::read).transpose()?.flatten();
+        Ok(LockFile {
+            path: file.map(Path::to_path_buf),
+            locks: stored.unwrap_or_default(),
+        })
+    }
+
+    /// Keep the locks in the loaded `file` from now on. The locks held
+    /// already stay and are joined by the ones the file holds.
+    pub(crate) fn switch(&self, file: LockFile) {
+        let mut locks = self.locks.lock().unwrap();
+        for lock in file.locks {
+            if !locks.iter().any(|held| held.token == lock.token) {
+                locks.push(lock);
+            }
+        }
+        self.file.set_path(file.path.as_deref());
+        self.save(&mut locks);
+    }
+
+    /// The lock system of the handler serving `root` at `prefix`.
+    pub(crate) fn lock_system(self: &Arc<Self>, root: &str, prefix: &str) -> Box<RootLocks> {
+        Box::new(RootLocks {
+            locks: self.clone(),
+            root: canonical_root(root),
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Block until the lock file is up to date.
+    pub(crate) fn flush(&self) {
+        self.file.flush();
+    }
+
+    /// Drop expired locks and write the rest to the lock file.
+    fn save(&self, locks: &mut Vec<StoredLock>) {
+        let now = SystemTime::now();
+        locks.retain(|lock| lock.expires > now);
+        self.file.save(locks);
+    }
+}
+
+/// [`Locks`] as seen by the handler of one folder.
+#[derive(Debug, Clone)]
+pub(crate) struct RootLocks {
+    locks: Arc<Locks>,
+    /// The served folder as an absolute path with symlinks resolved.
+    root: PathBuf,
+    prefix: String,
+}
+
+impl RootLocks {
+    fn file(&self, path: &DavPath) -> PathBuf {
+        self.root.join(path.as_rel_ospath())
+    }
+
+    fn to_dav_lock(&self, lock: &StoredLock) -> DavLock {
+        lock.to_dav_lock(&self.root, &self.prefix)
+    }
+
+    /// Run `f` on the unexpired locks.
+    fn read<T>(&self, f: impl FnOnce(&[&StoredLock]) -> T) -> T {
+        let now = SystemTime::now();
+        let locks = self.locks.locks.lock().unwrap();
+        let held = locks
+            .iter()
+            .filter(|lock| lock.expires > now)
+            .collect::<Vec<_>>();
+        f(&held)
+    }
+
+    /// Run `f` on all locks and save them.
+    fn update<T>(&self, f: impl FnOnce(&mut Vec<StoredLock>) -> T) -> T {
+        let mut locks = self.locks.locks.lock().unwrap();
+        let now = SystemTime::now();
+        locks.retain(|lock| lock.expires > now);
+        let result = f(&mut locks);
+        self.locks.save(&mut locks);
+        result
+    }
+
+    /// The lock with `token` taken at `path` or above it.
+    fn find<'a>(
+        &self,
+        locks: &'a mut [StoredLock],
+        path: &Path,
+        token: &str,
+    ) -> Option<&'a mut StoredLock> {
+        locks
+            .iter_mut()
+            .find(|lock| lock.token == token && path.starts_with(&lock.path))
+    }
+}
+
+impl DavLockSystem for RootLocks {
+    fn lock(
+        &self,
+        path: &DavPath,
+        principal: Option<&str>,
+        owner: Option<&Element>,
+        timeout: Option<Duration>,
+        shared: bool,
+        deep: bool,
+    ) -> Result<DavLock, DavLock> {
+        let target = self.file(path);
+        let timeout = timeout.map_or(MAX_LOCK_TIMEOUT, |t| t.min(MAX_LOCK_TIMEOUT));
+        let lock = StoredLock {
+            token: Uuid::new_v4().urn().to_string(),
+            path: target.clone(),
+            collection: path.is_collection(),
+            principal: principal.map(str::to_string),
+            owner: owner.and_then(|owner| {
+                let mut xml = Vec::new();
+                owner.write(&mut xml).ok()?;
+                String::from_utf8(xml).ok()
+            }),
+            timeout,
+            expires: SystemTime::now() + timeout,
+            shared,
+            deep,
+        };
+        let conflict = self.update(|locks| {
+            // shared locks only get along with each other
+            let conflict = locks.iter().find(|lock| {
+                (lock.covers(&target) || (deep && is_below(&lock.path, &target)))
+                    && !(lock.shared && shared)
+            });
+            match conflict {
+                Some(conflict) => Some(self.to_dav_lock(conflict)),
+                None => {
+                    locks.push(lock.clone());
+                    None
+                }
+            }
+        });
+        match conflict {
+            Some(conflict) => Err(conflict),
+            None => Ok(self.to_dav_lock(&lock)),
+        }
+    }
+
+    fn unlock(&self, path: &DavPath, token: &str) -> Result<(), ()> {
+        let target = self.file(path);
+        self.update(|locks| {
+            let token = self.find(locks, &target, token).ok_or(())?.token.clone();
+            locks.retain(|lock| lock.token != token);
+            Ok(())
+        })
+    }
+
+    fn refresh(
+        &self,
+        path: &DavPath,
+        token: &str,
+        timeout: Option<Duration>,
+    ) -> Result<DavLock, ()> {
+        let target = self.file(path);
+        let timeout = timeout.map_or(MAX_LOCK_TIMEOUT, |t| t.min(MAX_LOCK_TIMEOUT));
+        self.update(|locks| {
+            let lock = self.find(locks, &target, token).ok_or(())?;
+            lock.timeout = timeout;
+            lock.expires = SystemTime::now() + timeout;
+            Ok(self.to_dav_lock(lock))
+        })
+    }
+
+    fn check(
+        &self,
+        path: &DavPath,
+        principal: Option<&str>,
+        ignore_principal: bool,
+        deep: bool,
+        submitted_tokens: Vec<&str>,
+    ) -> Result<(), DavLock> {
+        let target = self.file(path);
+        let held = |lock: &StoredLock| {
+            submitted_tokens.contains(&lock.token.as_str())
+                && (ignore_principal || principal == lock.principal.as_deref())
+        };
+        self.read(|locks| {
+            let covering = locks
+                .iter()
+                .copied()
+                .filter(|lock| lock.covers(&target))
+                .collect::<Vec<_>>();
+            // holding one of several shared locks is enough
+            let holds_any = covering.iter().any(|lock| held(lock));
+            let below = locks
+                .iter()
+                .copied()
+                .filter(|lock| deep && is_below(&lock.path, &target));
+            covering
+                .iter()
+                .copied()
+                .find(|lock| !held(lock) && (!lock.shared || !holds_any))
+                .or_else(|| below.into_iter().find(|lock| !held(lock)))
+                .map(|conflict| self.to_dav_lock(conflict))
+        })
+        .map_or(Ok(()), Err)
+    }
+
+    fn discover(&self, path: &DavPath) -> Vec<DavLock> {
+        let target = self.file(path);
+        self.read(|locks| {
+            locks
+                .iter()
+                .filter(|lock| lock.covers(&target))
+                .map(|lock| self.to_dav_lock(lock))
+                .collect()
+        })
+    }
+
+    fn delete(&self, path: &DavPath) -> Result<(), ()> {
+        let target = self.file(path);
+        self.update(|locks| locks.retain(|lock| !lock.path.starts_with(&target)));
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::State,
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::TryStreamExt;
+
+use crate::auth::AuthFailed;
+
+/// Upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Methods counted under their own name. Any other is counted as `OTHER`,
+/// so clients cannot add label series at will.
+const METHODS: [&str; 14] = [
+    "GET",
+    "HEAD",
+    "PUT",
+    "POST",
+    "DELETE",
+    "OPTIONS",
+    "PATCH",
+    "PROPFIND",
+    "PROPPATCH",
+    "MKCOL",
+    "COPY",
+    "MOVE",
+    "LOCK",
+    "UNLOCK",
+];
+
+fn method_label(method: &Method) -> &'static str {
+    METHODS
+        .into_iter()
+        .find(|known| *known == method.as_str())
+        .unwrap_or("OTHER")
+}
+
+/// Counters of a [`DavServer`](crate::DavServer). They are kept across
+/// reloads and restarts of the same server.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    latency: Mutex<BTreeMap<String, Histogram>>,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    active_connections: AtomicI64,
+    auth_failures: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// A point in time copy of [`Metrics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    pub requests: Vec<RequestCount>,
+    pub latency: Vec<LatencyHistogram>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub active_connections: i64,
+    /// Requests whose credentials or share token were rejected; challenges
+    /// to requests without any are not counted.
+    pub auth_failures: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestCount {
+    pub method: String,
+    pub status: u16,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    pub method: String,
+    /// `(upper bound in seconds, cumulative count)` pairs.
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Metrics {
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let requests = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((method, status), count)| RequestCount {
+                method: method.clone(),
+                status: *status,
+                count: *count,
+            })
+            .collect();
+        let latency = self
+            .latency
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, histogram)| LatencyHistogram {
+                method: method.clone(),
+                buckets: LATENCY_BUCKETS.into_iter().zip(histogram.buckets).collect(),
+                sum: histogram.sum,
+                count: histogram.count,
+            })
+            .collect();
+
+        MetricsSnapshot {
+            requests,
+            latency,
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+
+        out.push_str("# HELP dav_requests_total Requests handled, by method and status.\n");
+        out.push_str("# TYPE dav_requests_total counter\n");
+        for r in &snapshot.requests {
+            let _ = writeln!(
+                out,
+                "dav_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                r.method, r.status, r.count
+            );
+        }
+
+        out.push_str(
+            "# HELP dav_request_duration_seconds Time until the response headers are sent.\n",
+        );
+        out.push_str("# TYPE dav_request_duration_seconds histogram\n");
+        for h in &snapshot.latency {
+            for (bound, count) in &h.buckets {
+                let _ = writeln!(
+                    out,
+                    "dav_request_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    h.method, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "dav_request_duration_seconds_bucket{{method=\"{}\",le=\"+Inf\"}} {}",
+                h.method, h.count
+            );
+            let _ = writeln!(
+                out,
+                "dav_request_duration_seconds_sum{{method=\"{}\"}} {}",
+                h.method, h.sum
+            );
+            let _ = writeln!(
+                out,
+                "dav_request_duration_seconds_count{{method=\"{}\"}} {}",
+                h.method, h.count
+            );
+        }
+
+        let scalars = [
+            (
+                "dav_received_bytes_total",
+                "counter",
+                "Request body bytes received.",
+                snapshot.bytes_in as i64,
+            ),
+            (
+                "dav_sent_bytes_total",
+                "counter",
+                "Response body bytes sent.",
+                snapshot.bytes_out as i64,
+            ),
+            (
+                "dav_active_connections",
+                "gauge",
+                "Open client connections.",
+                snapshot.active_connections,
+            ),
+            (
+                "dav_auth_failures_total",
+                "counter",
+                "Requests whose credentials or share token were rejected.",
+                snapshot.auth_failures as i64,
+            ),
+        ];
+        for (name, kind, help, value) in scalars {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        out
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// `auth_failed` tells whether the credentials or share token of the
+    /// request were rejected.
+    fn record_request(
+        &self,
+        method: &str,
+        status: StatusCode,
+        elapsed: Duration,
+        auth_failed: bool,
+    ) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), status.as_u16()))
+            .or_default() += 1;
+        self.latency
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+        if auth_failed {
+            self.auth_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Records method, status, latency and body sizes of every request.
+pub(crate) async fn metrics_middleware(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let start = Instant::now();
+    let method = method_label(req.method());
+
+    let req = req.map(|body| {
+        let metrics = metrics.clone();
+        Body::wrap_stream(body.inspect_ok(move |chunk| {
+            metrics
+                .bytes_in
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }))
+    });
+    let resp = next.run(req).await;
+    let auth_failed = resp.extensions().get::<AuthFailed>().is_some();
+    metrics.record_request(method, resp.status(), start.elapsed(), auth_failed);
+
+    resp.map(|body| {
+        axum::body::boxed(body.map_data(move |chunk: Bytes| {
+            metrics
+                .bytes_out
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            chunk
+        }))
+    })
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+};
+
+/// Who may use a [`Mount`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MountAccess {
+    /// The accounts of the [`DavConfig`](crate::DavConfig), like its root.
+    #[default]
+    Login,
+    /// Everyone may read, the accounts may make changes, see
+    /// [`DavConfig::anonymous_read`](crate::DavConfig::anonymous_read).
+    AnonymousRead,
+    /// Everyone, without logging in.
+    Public,
+}
+
+/// A folder served at a path of its own next to the
+/// [`DavConfig`](crate::DavConfig) root, see
+/// [`DavConfig::add_mount`](crate::DavConfig::add_mount). Accounts share
+/// the mount; their own folders do not apply below it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    /// A single segment like `/projects/`.
+    pub path: String,
+    pub root: String,
+    pub read_only: bool,
+    pub access: MountAccess,
+}
+
+impl Mount {
+    /// Serve `root` at `path` to the accounts of the server.
+    pub fn new(path: String, root: String) -> Self {
+        Mount {
+            path,
+            root,
+            read_only: false,
+            access: MountAccess::Login,
+        }
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn access(mut self, access: MountAccess) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// The segment of the path, e.g. `projects`.
+    fn name(&self) -> &str {
+        self.path.trim_matches('/')
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        let name = self.name();
+        // keeps the name usable in the mount list without escaping
+        if self.path != format!("/{}/", name)
+            || name.is_empty()
+            || name.starts_with('.')
+            || !name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+        {
+            return Err(anyhow::anyhow!(
+                "mount path {} must be a single segment like /projects/",
+                self.path
+            ));
+        }
+        crate::validate_root(&self.root).with_context(|| format!("mount {}", self.path))
+    }
+}
+
+/// Router answering for `/` when it only holds mounts: PROPFIND lists
+/// them as collections so clients can browse into them.
+pub(crate) fn mount_list_router(mounts: &[Mount]) -> axum::Router {
+    let names = Arc::new(
+        mounts
+            .iter()
+            .map(|mount| mount.name().to_string())
+            .collect::<Vec<_>>(),
+    );
+    axum::Router::new().route(
+        "/",
+        any(move |req: Request<Body>| {
+            let names = names.clone();
+            async move { mount_list(&names, &req) }
+        }),
+    )
+}
+
+fn mount_list<B>(names: &[String], req: &Request<B>) -> Response {
+    const ALLOW: &str = "OPTIONS,PROPFIND";
+    match req.method().as_str() {
+        "OPTIONS" => (StatusCode::OK, [("Allow", ALLOW), ("DAV", "1")]).into_response(),
+        "PROPFIND" => {
+            let depth_0 = req.headers().get("Depth").is_some_and(|depth| depth == "0");
+            let children = names.iter().filter(|_| !depth_0);
+            let mut body = String::from(
+                r#"<?xml version="1.0" encoding="utf-8"?><D:multistatus xmlns:D="DAV:">"#,
+            );
+            for (href, name) in std::iter::once(("/".to_string(), ""))
+                .chain(children.map(|name| (format!("/{}/", name), name.as_str())))
+            {
+                body.push_str(&format!(
+                    "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+                     <D:displayname>{}</D:displayname>\
+                     <D:resourcetype><D:collection/></D:resourcetype>\
+                     </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+                    href, name
+                ));
+            }
+            body.push_str("</D:multistatus>");
+            (
+                StatusCode::MULTI_STATUS,
+                [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+                body,
+            )
+                .into_response()
+        }
+        _ => (
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(header::ALLOW, ALLOW)],
+            "Method Not Allowed",
+        )
+            .into_response(),
+    }
+}
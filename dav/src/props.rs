@@ -0,0 +1,662 @@
+use std::{
+    fmt,
+    future::{self, Future},
+    io,
+    path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use axum::http::StatusCode;
+use dav_server::{
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, DavProp, FsError, FsFuture, FsStream,
+        OpenOptions, ReadDirMeta,
+    },
+    localfs::LocalFs,
+};
+use redb::{backends::InMemoryBackend, Database, ReadableTable, TableDefinition};
+use xmltree::Element;
+
+use crate::utils::canonical_root;
+
+/// Windows has no extended attributes, probing for them always fails there
+/// and the properties go to the database.
+#[cfg(not(unix))]
+mod xattr {
+    use std::{ffi::OsStr, io, iter, path::Path};
+
+    fn unsupported() -> io::Error {
+        io::ErrorKind::Unsupported.into()
+    }
+
+    pub(super) fn get(_: impl AsRef<Path>, _: impl AsRef<OsStr>) -> io::Result<Option<Vec<u8>>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn set(_: impl AsRef<Path>, _: impl AsRef<OsStr>, _: &[u8]) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn remove(_: impl AsRef<Path>, _: impl AsRef<OsStr>) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn list(_: impl AsRef<Path>) -> io::Result<iter::Empty<std::ffi::OsString>> {
+        Err(unsupported())
+    }
+}
+
+/// Prefix of the extended attributes holding dead properties.
+const XATTR_PREFIX: &str = "user.dav.";
+
+/// Property XML by file and [`prop_key`]. Files are keyed by their absolute
+/// path as by [`path_key`], so every root serving a file sees its properties.
+const PROPS: TableDefinition<PropKey, &[u8]> = TableDefinition::new("props");
+
+type PropKey = (&'static [u8], &'static str);
+
+/// A property of a file as path, key and XML.
+type Entry = (Vec<u8>, String, Vec<u8>);
+
+fn path_key(file: &Path) -> &[u8] {
+    file.as_os_str().as_encoded_bytes()
+}
+
+/// The property name in Clark notation, e.g. `{urn:example}tags`.
+fn prop_key(prop: &DavProp) -> String {
+    format!(
+        "{{{}}}{}",
+        prop.namespace.as_deref().unwrap_or(""),
+        prop.name
+    )
+}
+
+/// The path keys of the files below `path`, and none else, start with it.
+fn below(path: &[u8]) -> Vec<u8> {
+    let mut below = path.to_vec();
+    if !below.ends_with(MAIN_SEPARATOR_STR.as_bytes()) {
+        below.extend_from_slice(MAIN_SEPARATOR_STR.as_bytes());
+    }
+    below
+}
+
+fn is_within(path: &[u8], ancestor: &[u8]) -> bool {
+    path == ancestor || path.starts_with(&below(ancestor))
+}
+
+/// The properties of `path` and, if `deep`, of everything below it.
+fn subtree(
+    table: &impl ReadableTable<PropKey, &'static [u8]>,
+    path: &[u8],
+    deep: bool,
+) -> Result<Vec<Entry>> {
+    let below = below(path);
+    let mut entries = Vec::new();
+    let ranges = [(path, false), (below.as_slice(), true)];
+    for (start, is_below) in ranges.into_iter().take(if deep { 2 } else { 1 }) {
+        for entry in table.range((start, "")..)? {
+            let (key, xml) = entry?;
+            let (file, prop) = key.value();
+            let within = match is_below {
+                false => file == path,
+                true => file.starts_with(&below),
+            };
+            if !within {
+                break;
+            }
+            entries.push((file.to_vec(), prop.to_string(), xml.value().to_vec()));
+        }
+    }
+    Ok(entries)
+}
+
+/// A database in memory, with the property table.
+fn in_memory() -> Result<Database> {
+    let db = Database::builder().create_with_backend(InMemoryBackend::new())?;
+    create_table(&db)?;
+    Ok(db)
+}
+
+fn create_table(db: &Database) -> Result<()> {
+    let txn = db.begin_write()?;
+    txn.open_table(PROPS)?;
+    txn.commit()?;
+    Ok(())
+}
+
+/// Dead properties of the folders whose file system has no extended
+/// attributes, in an embedded database. The routers share them, so they
+/// survive a [`DavServer::reload`](crate::DavServer::reload); with a
+/// [`DavConfig::property_file`](crate::DavConfig::property_file) they
+/// survive a restart as well.
+pub(crate) struct Properties {
+    db: Mutex<PropertyDb>,
+}
+
+struct PropertyDb {
+    file: Option<PathBuf>,
+    db: Arc<Database>,
+}
+
+impl Default for Properties {
+    fn default() -> Self {
+        Self {
+            db: Mutex::new(PropertyDb {
+                file: None,
+                db: Arc::new(in_memory().expect("failed to create a database in memory")),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for Properties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Properties")
+            .field("file", &self.db.lock().unwrap().file)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A property database opened by [`Properties::load`], to be switched to.
+pub(crate) struct PropertyFile(PropertyDb);
+
+impl fmt::Debug for PropertyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PropertyFile").field(&self.0.file).finish()
+    }
+}
+
+impl Properties {
+    /// Keep the properties in `file` from now on, or in memory only. The
+    /// properties set already stay, the file only adds the ones they lack.
+    pub(crate) fn open(&self, file: Option<&Path>) -> Result<()> {
+        match self.load(file)? {
+            Some(file) => self.switch(file),
+            None => Ok(()),
+        }
+    }
+
+    /// Open `file` for [`Properties::switch`], `None` if it is open already;
+    /// a database cannot be opened twice.
+    pub(crate) fn load(&self, file: Option<&Path>) -> Result<Option<PropertyFile>> {
+        if self.db.lock().unwrap().file.as_deref() == file {
+            return Ok(None);
+        }
+        let db = match file {
+            Some(file) => {
+                let db = Database::create(file)
+                    .with_context(|| format!("failed to open {}", file.display()))?;
+                create_table(&db).with_context(|| format!("failed to open {}", file.display()))?;
+                db
+            }
+            None => in_memory()?,
+        };
+        Ok(Some(PropertyFile(PropertyDb {
+            file: file.map(Path::to_path_buf),
+            db: Arc::new(db),
+        })))
+    }
+
+    /// Keep the properties in the loaded `file` from now on, as by
+    /// [`Properties::open`]. Fails without switching if the properties set
+    /// already cannot be copied over.
+    pub(crate) fn switch(&self, file: PropertyFile) -> Result<()> {
+        let mut current = self.db.lock().unwrap();
+        merge(&current.db, &file.0.db).context("failed to copy the properties")?;
+        *current = file.0;
+        Ok(())
+    }
+
+    /// Keep the properties in memory and let go of the property file, so
+    /// the next server can open it.
+    pub(crate) fn close(&self) -> Result<()> {
+        self.open(None)
+    }
+
+    /// The file system of the handler serving `root`. Its properties go to
+    /// extended attributes if the file system of `root` has them.
+    pub(crate) fn file_system(self: &Arc<Self>, root: &str) -> Box<PropFs> {
+        let root = canonical_root(root);
+        let store = match xattr::get(&root, format!("{}probe", XATTR_PREFIX)) {
+            Ok(_) => Store::Xattr,
+            Err(_) => Store::Database(self.clone()),
+        };
+        Box::new(PropFs {
+            fs: LocalFs::new(&root, false, false, false),
+            root,
+            store,
+        })
+    }
+
+    fn database(&self) -> Arc<Database> {
+        self.db.lock().unwrap().db.clone()
+    }
+
+    fn read<T>(
+        &self,
+        f: impl FnOnce(&redb::ReadOnlyTable<PropKey, &'static [u8]>) -> Result<T>,
+    ) -> io::Result<T> {
+        let read = || {
+            let txn = self.database().begin_read()?;
+            f(&txn.open_table(PROPS)?)
+        };
+        read().map_err(io::Error::other)
+    }
+
+    fn write<T>(
+        &self,
+        f: impl FnOnce(&mut redb::Table<PropKey, &'static [u8]>) -> Result<T>,
+    ) -> io::Result<T> {
+        let write = || {
+            let txn = self.database().begin_write()?;
+            let result = f(&mut txn.open_table(PROPS)?)?;
+            txn.commit()?;
+            anyhow::Ok(result)
+        };
+        write().map_err(io::Error::other)
+    }
+}
+
+/// Copy the properties of `from` that `to` lacks.
+fn merge(from: &Database, to: &Database) -> Result<()> {
+    let read = from.begin_read()?;
+    let source = read.open_table(PROPS)?;
+    let write = to.begin_write()?;
+    {
+        let mut target = write.open_table(PROPS)?;
+        for entry in source.iter()? {
+            let (key, xml) = entry?;
+            if target.get(key.value())?.is_none() {
+                target.insert(key.value(), xml.value())?;
+            }
+        }
+    }
+    write.commit()?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+enum Store {
+    Xattr,
+    Database(Arc<Properties>),
+}
+
+impl Store {
+    /// The properties of `file` as key and XML.
+    fn list(&self, file: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+        match self {
+            Store::Xattr => {
+                let file = xattr_path(file);
+                let mut props = Vec::new();
+                for name in xattr::list(&file)? {
+                    let Some(key) = name.to_str().and_then(|n| n.strip_prefix(XATTR_PREFIX)) else {
+                        continue;
+                    };
+                    if let Some(xml) = xattr::get(&file, &name)? {
+                        props.push((key.to_string(), xml));
+                    }
+                }
+                Ok(props)
+            }
+            Store::Database(properties) => properties.read(|table| {
+                Ok(subtree(table, path_key(file), false)?
+                    .into_iter()
+                    .map(|(_, key, xml)| (key, xml))
+                    .collect())
+            }),
+        }
+    }
+
+    fn get(&self, file: &Path, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Store::Xattr => xattr::get(xattr_path(file), format!("{}{}", XATTR_PREFIX, key)),
+            Store::Database(properties) => properties.read(|table| {
+                Ok(table
+                    .get((path_key(file), key))?
+                    .map(|xml| xml.value().to_vec()))
+            }),
+        }
+    }
+
+    fn set(&self, file: &Path, key: &str, xml: &[u8]) -> io::Result<()> {
+        match self {
+            Store::Xattr => xattr::set(xattr_path(file), format!("{}{}", XATTR_PREFIX, key), xml),
+            Store::Database(properties) => properties.write(|table| {
+                table.insert((path_key(file), key), xml)?;
+                Ok(())
+            }),
+        }
+    }
+
+    fn remove(&self, file: &Path, key: &str) -> io::Result<()> {
+        match self {
+            Store::Xattr => {
+                let file = xattr_path(file);
+                let name = format!("{}{}", XATTR_PREFIX, key);
+                // removing a property that is not set is no error
+                match xattr::get(&file, &name)? {
+                    Some(_) => xattr::remove(&file, &name),
+                    None => Ok(()),
+                }
+            }
+            Store::Database(properties) => properties.write(|table| {
+                table.remove((path_key(file), key))?;
+                Ok(())
+            }),
+        }
+    }
+
+    /// Drop the properties of `file` and everything below it after it was
+    /// deleted. Extended attributes go with the files.
+    fn forget(&self, file: &Path) -> io::Result<()> {
+        let Store::Database(properties) = self else {
+            return Ok(());
+        };
+        properties.write(|table| {
+            for (path, key, _) in subtree(&*table, path_key(file), true)? {
+                table.remove((path.as_slice(), key.as_str()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Move the properties of `from` and everything below it to `to` after
+    /// a rename. Extended attributes go with the files.
+    fn relocate(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let Store::Database(properties) = self else {
+            return Ok(());
+        };
+        let (from, to) = (path_key(from), path_key(to));
+        properties.write(|table| {
+            for (path, key, _) in subtree(&*table, to, true)? {
+                table.remove((path.as_slice(), key.as_str()))?;
+            }
+            for (path, key, xml) in subtree(&*table, from, true)? {
+                table.remove((path.as_slice(), key.as_str()))?;
+                let moved = [to, &path[from.len()..]].concat();
+                table.insert((moved.as_slice(), key.as_str()), xml.as_slice())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Copy the properties of `from` and, if `deep`, everything below it to
+    /// `to`, which the copy of the files themselves leaves behind.
+    fn copy(&self, from: &Path, to: &Path, deep: bool) -> io::Result<()> {
+        match self {
+            Store::Xattr => copy_xattrs(&xattr_path(from), to, to, deep),
+            Store::Database(properties) => {
+                let (from, to) = (path_key(from), path_key(to));
+                properties.write(|table| {
+                    // a folder copied into itself is not copied again
+                    for (path, key, xml) in subtree(&*table, from, deep)? {
+                        if !is_within(&path, to) {
+                            let copied = [to, &path[from.len()..]].concat();
+                            table.insert((copied.as_slice(), key.as_str()), xml.as_slice())?;
+                        }
+                    }
+                    Ok(())
+                })
+            }
+        }
+    }
+}
+
+/// Symlinks cannot have user attributes, the files they point to can.
+fn xattr_path(file: &Path) -> PathBuf {
+    file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Copy the properties in the extended attributes of `from` to `to`, and
+/// if `deep` those of everything below `from` except `skip`.
+fn copy_xattrs(from: &Path, to: &Path, skip: &Path, deep: bool) -> io::Result<()> {
+    for name in xattr::list(from)? {
+        if !name.to_str().is_some_and(|n| n.starts_with(XATTR_PREFIX)) {
+            continue;
+        }
+        if let Some(xml) = xattr::get(from, &name)? {
+            xattr::set(to, &name, &xml)?;
+        }
+    }
+    if !deep || !from.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.path() != skip {
+            copy_xattrs(&entry.path(), &to.join(entry.file_name()), skip, deep)?;
+        }
+    }
+    Ok(())
+}
+
+/// [`LocalFs`] keeping the dead properties clients set with PROPPATCH.
+/// The Win32 times and attributes of Windows clients are live properties
+/// of dav-server and never get here.
+#[derive(Clone)]
+pub(crate) struct PropFs {
+    fs: Box<LocalFs>,
+    /// The served folder as an absolute path with symlinks resolved.
+    root: PathBuf,
+    store: Store,
+}
+
+impl PropFs {
+    fn file(&self, path: &DavPath) -> PathBuf {
+        self.root.join(path.as_rel_ospath())
+    }
+
+    /// Run `f` with the store on a blocking thread, extended attributes and
+    /// the database both wait for the disk.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Store) -> io::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || f(&store))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    fn list(
+        &self,
+        path: &DavPath,
+    ) -> impl Future<Output = io::Result<Vec<(String, Vec<u8>)>>> + '_ {
+        let file = self.file(path);
+        self.blocking(move |store| store.list(&file))
+    }
+
+    fn get(
+        &self,
+        path: &DavPath,
+        key: String,
+    ) -> impl Future<Output = io::Result<Option<Vec<u8>>>> + '_ {
+        let file = self.file(path);
+        self.blocking(move |store| store.get(&file, &key))
+    }
+
+    async fn patch(&self, path: &DavPath, key: String, xml: Option<Vec<u8>>) -> io::Result<()> {
+        let file = self.file(path);
+        self.blocking(move |store| match xml {
+            Some(xml) => store.set(&file, &key, &xml),
+            None => store.remove(&file, &key),
+        })
+        .await
+    }
+
+    async fn forget(&self, path: &DavPath) {
+        let file = self.file(path);
+        if let Err(e) = self.blocking(move |store| store.forget(&file)).await {
+            tracing::warn!(path = %path, "failed to drop properties: {}", e);
+        }
+    }
+
+    async fn relocate(&self, from: &DavPath, to: &DavPath) {
+        let (source, target) = (self.file(from), self.file(to));
+        if let Err(e) = self
+            .blocking(move |store| store.relocate(&source, &target))
+            .await
+        {
+            tracing::warn!(from = %from, to = %to, "failed to move properties: {}", e);
+        }
+    }
+
+    async fn copy_props(&self, from: &DavPath, to: &DavPath, deep: bool) {
+        let (source, target) = (self.file(from), self.file(to));
+        if let Err(e) = self
+            .blocking(move |store| store.copy(&source, &target, deep))
+            .await
+        {
+            tracing::warn!(from = %from, to = %to, "failed to copy properties: {}", e);
+        }
+    }
+
+    /// Copy the properties of `from` and, if `deep`, everything below it to
+    /// `to` after a COPY of a folder. dav-server copies folders by creating
+    /// new ones, which leaves their properties behind.
+    pub(crate) async fn copy_folder_props(&self, from: &DavPath, to: &DavPath, deep: bool) {
+        let source = self.file(from);
+        if tokio::fs::metadata(&source)
+            .await
+            .is_ok_and(|meta| meta.is_dir())
+        {
+            self.copy_props(from, to, deep).await;
+        }
+    }
+}
+
+impl DavFileSystem for PropFs {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        self.fs.open(path, options)
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        meta: ReadDirMeta,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        self.fs.read_dir(path, meta)
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        self.fs.metadata(path)
+    }
+
+    fn symlink_metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        self.fs.symlink_metadata(path)
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        self.fs.create_dir(path)
+    }
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.fs.remove_dir(path).await?;
+            self.forget(path).await;
+            Ok(())
+        })
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.fs.remove_file(path).await?;
+            self.forget(path).await;
+            Ok(())
+        })
+    }
+
+    fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.fs.rename(from, to).await?;
+            self.relocate(from, to).await;
+            Ok(())
+        })
+    }
+
+    fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.fs.copy(from, to).await?;
+            self.copy_props(from, to, false).await;
+            Ok(())
+        })
+    }
+
+    fn set_accessed<'a>(&'a self, path: &'a DavPath, tm: SystemTime) -> FsFuture<'a, ()> {
+        self.fs.set_accessed(path, tm)
+    }
+
+    fn set_modified<'a>(&'a self, path: &'a DavPath, tm: SystemTime) -> FsFuture<'a, ()> {
+        self.fs.set_modified(path, tm)
+    }
+
+    fn have_props<'a>(
+        &'a self,
+        _path: &'a DavPath,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(future::ready(true))
+    }
+
+    fn patch_props<'a>(
+        &'a self,
+        path: &'a DavPath,
+        patch: Vec<(bool, DavProp)>,
+    ) -> FsFuture<'a, Vec<(StatusCode, DavProp)>> {
+        Box::pin(async move {
+            let mut patched = Vec::with_capacity(patch.len());
+            for (set, mut prop) in patch {
+                let key = prop_key(&prop);
+                let xml = prop.xml.take().filter(|_| set);
+                let status = match self.patch(path, key.clone(), xml).await {
+                    Ok(()) => StatusCode::OK,
+                    Err(e) => {
+                        tracing::warn!(path = %path, "failed to store property {}: {}", key, e);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    }
+                };
+                patched.push((status, prop));
+            }
+            Ok(patched)
+        })
+    }
+
+    fn get_props<'a>(&'a self, path: &'a DavPath, do_content: bool) -> FsFuture<'a, Vec<DavProp>> {
+        Box::pin(async move {
+            Ok(self
+                .list(path)
+                .await?
+                .into_iter()
+                .filter_map(|(_, xml)| {
+                    // the element tells name, prefix and namespace
+                    let elem = Element::parse(xml.as_slice()).ok()?;
+                    Some(DavProp {
+                        name: elem.name,
+                        prefix: elem.prefix,
+                        namespace: elem.namespace,
+                        xml: do_content.then_some(xml),
+                    })
+                })
+                .collect())
+        })
+    }
+
+    fn get_prop<'a>(&'a self, path: &'a DavPath, prop: DavProp) -> FsFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            self.get(path, prop_key(&prop))
+                .await?
+                .ok_or(FsError::NotFound)
+        })
+    }
+
+    fn get_quota(&self) -> FsFuture<'_, (u64, Option<u64>)> {
+        self.fs.get_quota()
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::{header, request::Parts, Method};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{json_file::JsonFile, Identity, Permission, REDACTED};
+
+/// Path segment below the `http_path` prefix that share tokens are accepted
+/// in, as in `/_share/<token>/file.txt`.
+pub(crate) const SHARE_SEGMENT: &str = "_share";
+
+/// What a share token allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareScope {
+    /// Browse and download.
+    Read,
+    /// Everything an account with [`Permission::Full`](crate::Permission::Full)
+    /// may do.
+    Write,
+}
+
+/// A folder handed out with a token instead of an account, see
+/// [`ShareKey::mint`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    /// Identifies the share to [`DavConfig::revoke_share`](crate::DavConfig::revoke_share)
+    /// and [`DavServer::share_uses`](crate::DavServer::share_uses).
+    pub id: String,
+    /// Folder the token gives access to, relative to the
+    /// [`DavConfig`](crate::DavConfig) root, e.g. `/photos`.
+    pub path: String,
+    pub expires: SystemTime,
+    pub scope: ShareScope,
+    /// How many files may be downloaded or uploaded with the token.
+    pub max_uses: Option<u32>,
+}
+
+impl Share {
+    /// A share with a random id and no use limit.
+    pub fn new(path: String, expires: SystemTime, scope: ShareScope) -> Self {
+        let mut id = [0; 9];
+        OsRng.fill_bytes(&mut id);
+        Share {
+            id: URL_SAFE_NO_PAD.encode(id),
+            path,
+            expires,
+            scope,
+            max_uses: None,
+        }
+    }
+
+    pub fn max_uses(mut self, max_uses: u32) -> Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    fn payload(&self) -> String {
+        let expires = self
+            .expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let scope = match self.scope {
+            ShareScope::Read => "r",
+            ShareScope::Write => "w",
+        };
+        let max_uses = self.max_uses.map(|n| n.to_string()).unwrap_or_default();
+        [
+            self.id.as_str(),
+            &self.path,
+            &expires.to_string(),
+            scope,
+            &max_uses,
+        ]
+        .join("\n")
+    }
+
+    fn from_payload(payload: &str) -> Option<Self> {
+        let mut fields = payload.split('\n');
+        let share = Share {
+            id: fields.next()?.to_string(),
+            path: fields.next()?.to_string(),
+            expires: UNIX_EPOCH + Duration::from_secs(fields.next()?.parse().ok()?),
+            scope: match fields.next()? {
+                "r" => ShareScope::Read,
+                "w" => ShareScope::Write,
+                _ => return None,
+            },
+            max_uses: match fields.next()? {
+                "" => None,
+                n => Some(n.parse().ok()?),
+            },
+        };
+        fields.next().is_none().then_some(share)
+    }
+
+    /// The folder of the share below `root`, unless its path tries to
+    /// leave `root`.
+    pub(crate) fn root(&self, root: &str) -> Option<String> {
+        let path = Path::new(self.path.trim_start_matches('/'));
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return None;
+        }
+        Path::new(root).join(path).to_str().map(str::to_string)
+    }
+}
+
+/// Secret that share tokens are signed with. Tokens stay valid across
+/// restarts as long as the key does.
+#[derive(Clone, PartialEq)]
+pub struct ShareKey(Vec<u8>);
+
+impl std::fmt::Debug for ShareKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ShareKey").field(&REDACTED).finish()
+    }
+}
+
+impl ShareKey {
+    pub fn new(key: Vec<u8>) -> Self {
+        ShareKey(key)
+    }
+
+    /// A random key, to be stored by the caller.
+    pub fn generate() -> Self {
+        let mut key = vec![0; 32];
+        OsRng.fill_bytes(&mut key);
+        ShareKey(key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::new_from_slice(&self.0).expect("hmac takes keys of any length")
+    }
+
+    /// A token granting access to `share`, to be sent as `Bearer` token or
+    /// put into a link made with [`DavConfig::share_path`](crate::DavConfig::share_path).
+    pub fn mint(&self, share: &Share) -> String {
+        let payload = share.payload();
+        let signature = self.mac().chain_update(&payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// The share `token` was minted for, if it was signed with this key.
+    /// Expiry, revocation and uses are not checked.
+    pub(crate) fn verify(&self, token: &str) -> Option<Share> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.mac()
+            .chain_update(&payload)
+            .verify_slice(&signature)
+            .ok()?;
+        Share::from_payload(std::str::from_utf8(&payload).ok()?)
+    }
+}
+
+/// How often each share has been used, kept across reloads; with a
+/// [`DavConfig::share_use_file`](crate::DavConfig::share_use_file) across
+/// restarts as well.
+#[derive(Debug, Default)]
+pub(crate) struct ShareUses {
+    uses: Mutex<HashMap<String, u32>>,
+    file: JsonFile,
+}
+
+/// A share use file read by [`ShareUses::load`], to be switched to.
+#[derive(Debug)]
+pub(crate) struct ShareUseFile {
+    path: Option<PathBuf>,
+    uses: HashMap<String, u32>,
+}
+
+impl ShareUses {
+    /// Read `file` for [`ShareUses::switch`].
+    pub(crate) fn load(file: Option<&Path>) -> Result<ShareUseFile> {
+        let stored: Option<HashMap<String, u32>> = file.map(JsonFile::read).transpose()?.flatten();
+        Ok(ShareUseFile {
+            path: file.map(Path::to_path_buf),
+            uses: stored.unwrap_or_default(),
+        })
+    }
+
+    /// Keep the uses in the loaded `file` from now on. A share used more
+    /// often than the file says keeps its count.
+    pub(crate) fn switch(&self, file: ShareUseFile) {
+        let mut uses = self.uses.lock().unwrap();
+        for (id, count) in file.uses {
+            let own = uses.entry(id).or_default();
+            *own = (*own).max(count);
+        }
+        self.file.set_path(file.path.as_deref());
+        self.file.save(&*uses);
+    }
+
+    /// Block until the share use file is up to date.
+    pub(crate) fn flush(&self) {
+        self.file.flush();
+    }
+
+    pub(crate) fn get(&self, id: &str) -> u32 {
+        self.uses.lock().unwrap().get(id).copied().unwrap_or(0)
+    }
+
+    /// Count a use of `share`, unless it has been used up. The use is
+    /// [`given back`](Self::give_back) if the request fails.
+    pub(crate) fn try_use(&self, share: &Share) -> bool {
+        let mut uses = self.uses.lock().unwrap();
+        let count = uses.entry(share.id.clone()).or_default();
+        if share.max_uses.is_some_and(|max| *count >= max) {
+            return false;
+        }
+        *count += 1;
+        self.file.save(&*uses);
+        true
+    }
+
+    /// Undo a use of the share `id` counted for a request that failed.
+    pub(crate) fn give_back(&self, id: &str) {
+        let mut uses = self.uses.lock().unwrap();
+        if let Some(count) = uses.get_mut(id) {
+            *count = count.saturating_sub(1);
+            self.file.save(&*uses);
+        }
+    }
+}
+
+/// `path` with the share token in its [`SHARE_SEGMENT`] replaced, so logs and
+/// events do not hand out access to the share.
+pub(crate) fn redact_token(path: &str) -> Cow<'_, str> {
+    let segment = format!("/{}/", SHARE_SEGMENT);
+    let Some(start) = path.find(&segment).map(|i| i + segment.len()) else {
+        return Cow::Borrowed(path);
+    };
+    let end = path[start..].find('/').map_or(path.len(), |i| start + i);
+    Cow::Owned(format!("{}{}{}", &path[..start], REDACTED, &path[end..]))
+}
+
+/// Accepts share tokens as `Bearer` token or in the [`SHARE_SEGMENT`] of the
+/// path.
+#[derive(Debug)]
+pub(crate) struct ShareAuth {
+    pub(crate) key: ShareKey,
+    /// The [`DavConfig`](crate::DavConfig) root the shares are relative to.
+    pub(crate) root: String,
+    /// `http_path` followed by the share segment and a slash.
+    pub(crate) segment_prefix: String,
+    pub(crate) revoked: HashSet<String>,
+    pub(crate) uses: Arc<ShareUses>,
+}
+
+/// The part of the request path up to and including the share token, if the
+/// token was given in the path. Handlers strip it instead of `http_path` and
+/// are dropped once the share expires.
+#[derive(Debug, Clone)]
+pub(crate) struct SharePrefix {
+    pub(crate) prefix: String,
+    pub(crate) expires: SystemTime,
+}
+
+/// What a valid share token grants a request.
+#[derive(Debug)]
+pub(crate) struct ShareGrant {
+    pub(crate) identity: Identity,
+    pub(crate) expires: SystemTime,
+    /// The share whose use the request counts. A request that fails
+    /// [gives it back](ShareUses::give_back).
+    pub(crate) counted: Option<String>,
+}
+
+impl ShareAuth {
+    /// The share token of a request, along with the prefix to strip if it
+    /// was given in the path.
+    pub(crate) fn token<'a>(&self, req: &'a Parts) -> Option<(&'a str, Option<String>)> {
+        if let Some(rest) = req.uri.path().strip_prefix(&self.segment_prefix) {
+            let token = rest.split('/').next().unwrap_or_default();
+            let prefix = format!("{}{}", self.segment_prefix, token);
+            return Some((token, Some(prefix)));
+        }
+        let auth = req.headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = auth.split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("Bearer")
+            .then(|| (token.trim(), None))
+    }
+
+    /// What `token` grants a request with `method`, unless it is forged,
+    /// expired, revoked or used up.
+    pub(crate) fn authorize(&self, token: &str, method: &Method) -> Option<ShareGrant> {
+        let share = self.key.verify(token)?;
+        if share.expires <= SystemTime::now() || self.revoked.contains(&share.id) {
+            return None;
+        }
+        let permission = match share.scope {
+            ShareScope::Read => Permission::ReadOnly,
+            ShareScope::Write => Permission::Full,
+        };
+        let identity = Identity {
+            user: format!("share:{}", share.id),
+            root: Some(share.root(&self.root)?),
+            permission,
+        };
+        // only transfers count as a use, browsing does not
+        let counted =
+            permission.allows(method) && (*method == Method::GET || *method == Method::PUT);
+        if counted && !self.uses.try_use(&share) {
+            return None;
+        }
+        Some(ShareGrant {
+            identity,
+            expires: share.expires,
+            counted: counted.then_some(share.id),
+        })
+    }
+}
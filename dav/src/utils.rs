@@ -28,4 +28,13 @@ impl<T> WithMutProcedure for T {
         f(&mut self);
         self
     }
-}
\ No newline at end of file
+}
+
+/// `root` as an absolute path with symlinks resolved, so folders served
+/// under different roots agree on the paths of the files they share. `root`
+/// as given if it cannot be resolved.
+pub(crate) fn canonical_root(root: &str) -> std::path::PathBuf {
+    std::path::Path::new(root)
+        .canonicalize()
+        .unwrap_or_else(|_| root.into())
+}
@@ -0,0 +1,134 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{
+    fmt::MakeWriter, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
+
+const DEFAULT_LEVEL: &str = "info";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogConfig {
+    /// An `EnvFilter` directive such as `info` or `dav::access=debug`.
+    pub level: Option<String>,
+    /// Also append the log to this file.
+    pub file: Option<String>,
+    /// Write the file as JSON lines instead of text.
+    #[serde(default)]
+    pub json: bool,
+}
+
+impl LogConfig {
+    fn filter(&self) -> Result<EnvFilter, String> {
+        EnvFilter::try_new(self.level.as_deref().unwrap_or(DEFAULT_LEVEL))
+            .map_err(|e| format!("Invalid log level: {}", e))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.filter().map(|_| ())
+    }
+}
+
+/// The global subscriber. Logs go to stdout and, if configured, to a file;
+/// both can be changed at runtime with [`Logging::apply`].
+#[derive(Debug)]
+pub struct Logging {
+    filter: reload::Handle<EnvFilter, Registry>,
+    output: Arc<Mutex<LogFile>>,
+}
+
+#[derive(Debug, Default)]
+struct LogFile {
+    file: Option<File>,
+    json: bool,
+}
+
+impl Logging {
+    pub fn init() -> Self {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new(DEFAULT_LEVEL));
+        let output = Arc::new(Mutex::new(LogFile::default()));
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(tracing_subscriber::fmt::layer())
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(FileWriter {
+                        output: output.clone(),
+                        json: false,
+                    }),
+            )
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_writer(FileWriter {
+                        output: output.clone(),
+                        json: true,
+                    }),
+            )
+            .init();
+
+        Logging {
+            filter: handle,
+            output,
+        }
+    }
+
+    pub fn apply(&self, config: &LogConfig) -> Result<(), String> {
+        let filter = config.filter()?;
+        let file = config
+            .file
+            .as_ref()
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .transpose()
+            .map_err(|e| format!("Failed to open log file: {}", e))?;
+
+        self.filter.reload(filter).map_err(|e| e.to_string())?;
+        *self.output.lock() = LogFile {
+            file,
+            json: config.json,
+        };
+        Ok(())
+    }
+}
+
+/// Writes to the log file if one is open and its format matches `json`,
+/// otherwise discards the output.
+#[derive(Debug, Clone)]
+struct FileWriter {
+    output: Arc<Mutex<LogFile>>,
+    json: bool,
+}
+
+impl<'a> MakeWriter<'a> for FileWriter {
+    type Writer = FileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.output.lock();
+        let json = output.json;
+        match output.file.as_mut() {
+            Some(file) if json == self.json => file.write(buf),
+            _ => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.output.lock().file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,39 +1,121 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::net::{IpAddr, Ipv6Addr};
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use dav::{utils::WithMutProcedure, TlsConfig};
+use futures_util::FutureExt;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, oneshot};
 
 mod cert;
+mod logging;
 use cert::*;
+use logging::{LogConfig, Logging};
 use tauri::Manager;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Config {
-    ip: Option<String>,
-    port: Option<u16>,
+    #[serde(default)]
+    listeners: Vec<ListenerConfig>,
     root: Option<String>,
-    auth: Option<(String, String)>,
+    #[serde(default)]
+    users: Vec<UserConfig>,
+    /// Apache htpasswd file whose users may log in as well.
+    #[serde(default)]
+    htpasswd_file: Option<String>,
+    /// Everyone may browse and download, only users may make changes.
+    #[serde(default)]
+    anonymous_read: bool,
+    /// Nobody may change files, whatever their permission.
+    #[serde(default)]
+    read_only: bool,
+    /// Further folders served at paths of their own.
+    #[serde(default)]
+    mounts: Vec<MountConfig>,
+    /// Keep WebDAV locks in the config dir so they survive a restart.
+    #[serde(default)]
+    persist_locks: bool,
 
     enable_tls: bool,
+    /// Port of a plain HTTP listener that redirects to the TLS listener.
+    #[serde(default)]
+    https_redirect_port: Option<u16>,
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     tls_cert: Option<TlsCert>,
+    /// PEM file of the CAs that client certificates are verified against.
+    #[serde(default)]
+    client_ca_file: Option<String>,
+    /// Refuse TLS clients without a certificate signed by the client CA.
+    #[serde(default)]
+    require_client_cert: bool,
+    #[serde(default)]
+    log: LogConfig,
+    #[serde(default)]
+    lockout: LockoutConfig,
+    #[serde(default)]
+    ip_filter: IpFilterConfig,
+    /// Signs share tokens; generated when the first share is created.
+    #[serde(skip)]
+    share_key: Option<Vec<u8>>,
+    /// Managed by `create_share` and `revoke_share` only.
+    #[serde(skip_deserializing)]
+    shares: Vec<ShareConfig>,
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("listeners", &self.listeners)
+            .field("root", &self.root)
+            .field("users", &self.users)
+            .field("htpasswd_file", &self.htpasswd_file)
+            .field("anonymous_read", &self.anonymous_read)
+            .field("read_only", &self.read_only)
+            .field("mounts", &self.mounts)
+            .field("persist_locks", &self.persist_locks)
+            .field("enable_tls", &self.enable_tls)
+            .field("https_redirect_port", &self.https_redirect_port)
+            .field("tls_cert", &self.tls_cert)
+            .field("client_ca_file", &self.client_ca_file)
+            .field("require_client_cert", &self.require_client_cert)
+            .field("log", &self.log)
+            .field("lockout", &self.lockout)
+            .field("ip_filter", &self.ip_filter)
+            .field("share_key", &self.share_key.as_ref().map(|_| "<redacted>"))
+            .field("shares", &self.shares)
+            .finish()
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            ip: None,
-            port: None,
+            listeners: Vec::new(),
             root: None,
-            auth: None,
+            users: Vec::new(),
+            htpasswd_file: None,
+            anonymous_read: false,
+            read_only: false,
+            mounts: Vec::new(),
+            persist_locks: false,
 
             enable_tls: false,
+            https_redirect_port: None,
             tls_cert: None,
+            client_ca_file: None,
+            require_client_cert: false,
+            log: LogConfig::default(),
+            lockout: LockoutConfig::default(),
+            ip_filter: IpFilterConfig::default(),
+            share_key: None,
+            shares: Vec::new(),
         }
     }
 }
@@ -44,9 +126,13 @@ impl Config {
     }
 
     fn validate(&self) -> Result<(), String> {
-        if let Some(ip) = &self.ip {
-            if ip.parse::<IpAddr>().is_err() {
-                return Err("Invalid IP address".to_string());
+        self.log.validate()?;
+
+        for listener in &self.listeners {
+            if let Some(ip) = &listener.ip {
+                if ip.parse::<IpAddr>().is_err() {
+                    return Err("Invalid IP address".to_string());
+                }
             }
         }
 
@@ -56,11 +142,53 @@ impl Config {
                     return Err("Root path does not exist".to_string());
                 }
             }
+            // every user has a folder of its own
+            None if !self.users.is_empty() && self.users.iter().all(|u| u.root.is_some()) => {}
+            None if !self.mounts.is_empty() => {}
             None => {
                 return Err("Root path is not set".to_string());
             }
         }
 
+        if self.root.is_some() && !self.mounts.is_empty() {
+            return Err("Clear the root path to use mounts".to_string());
+        }
+        for mount in &self.mounts {
+            if !std::path::PathBuf::from(&mount.root).exists() {
+                return Err(format!("Folder of mount {} does not exist", mount.path));
+            }
+        }
+
+        self.ip_filter.to_dav_ip_filter()?;
+
+        if let Some(htpasswd) = &self.htpasswd_file {
+            if !std::path::PathBuf::from(htpasswd).exists() {
+                return Err("htpasswd file does not exist".to_string());
+            }
+        }
+
+        if let Some(ca) = &self.client_ca_file {
+            if !std::path::PathBuf::from(ca).exists() {
+                return Err("Client CA file does not exist".to_string());
+            }
+        }
+
+        for (i, user) in self.users.iter().enumerate() {
+            if user.user.is_empty() || (user.password_hash.is_none() && user.client_cert.is_none())
+            {
+                return Err("User name and password must not be empty".to_string());
+            }
+            user.to_dav_account()?;
+            if self.users[..i].iter().any(|u| u.user == user.user) {
+                return Err(format!("User {} is listed twice", user.user));
+            }
+            if let Some(root) = &user.root {
+                if !std::path::PathBuf::from(root).exists() {
+                    return Err(format!("Root path of user {} does not exist", user.user));
+                }
+            }
+        }
+
         // if self.enable_tls {
         //     if let Some(cert) = &self.tls_cert {
         //         match cert {
@@ -90,7 +218,261 @@ impl Config {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LockoutConfig {
+    /// Failed logins before an address or user is banned; 0 disables the
+    /// lockout.
+    threshold: u32,
+    ban_secs: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        let policy = dav::lockout::LockoutPolicy::default();
+        LockoutConfig {
+            threshold: policy.threshold,
+            ban_secs: policy.ban_duration.as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IpFilterConfig {
+    /// Only accept loopback and private network addresses.
+    #[serde(default)]
+    private_lan_only: bool,
+    /// CIDR ranges to accept, e.g. `10.0.0.0/8`; with `private_lan_only`
+    /// in addition to the private ones.
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+impl IpFilterConfig {
+    fn to_dav_ip_filter(&self) -> Result<dav::ip_filter::IpFilter, String> {
+        let parse = |nets: &[String]| {
+            nets.iter()
+                .map(|net| net.parse().map_err(|e| format!("{:#}", e)))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mut filter = if self.private_lan_only {
+            dav::ip_filter::IpFilter::private_lan()
+        } else {
+            dav::ip_filter::IpFilter::default()
+        };
+        filter.allow.extend(parse(&self.allow)?);
+        filter.deny.extend(parse(&self.deny)?);
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ShareScope {
+    Read,
+    Write,
+}
+
+impl ShareScope {
+    fn to_dav_share_scope(self) -> dav::ShareScope {
+        match self {
+            ShareScope::Read => dav::ShareScope::Read,
+            ShareScope::Write => dav::ShareScope::Write,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShareConfig {
+    id: String,
+    /// Folder below `Config::root`.
+    path: String,
+    /// Milliseconds since the Unix epoch.
+    expires: u64,
+    scope: ShareScope,
+    max_uses: Option<u32>,
+    token: String,
+    revoked: bool,
+    /// Files transferred with the token so far, filled in by `list_shares`.
+    uses: u32,
+}
+
+impl std::fmt::Debug for ShareConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShareConfig")
+            .field("id", &self.id)
+            .field("path", &self.path)
+            .field("expires", &self.expires)
+            .field("scope", &self.scope)
+            .field("max_uses", &self.max_uses)
+            .field("token", &"<redacted>")
+            .field("revoked", &self.revoked)
+            .finish()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserConfig {
+    user: String,
+    /// A new password as typed by the user; it is replaced by
+    /// `password_hash` as soon as the config is updated.
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+    /// argon2 PHC string or bcrypt hash of the password.
+    #[serde(default)]
+    password_hash: Option<String>,
+    /// Serve this user from its own folder instead of `Config::root`.
+    root: Option<String>,
+    #[serde(default)]
+    permission: Permission,
+    /// Subject common name or alternative name of the client certificate
+    /// that logs in as this user.
+    #[serde(default)]
+    client_cert: Option<String>,
+}
+
+impl std::fmt::Debug for UserConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserConfig")
+            .field("user", &self.user)
+            .field("password", &"<redacted>")
+            .field("password_hash", &"<redacted>")
+            .field("root", &self.root)
+            .field("permission", &self.permission)
+            .field("client_cert", &self.client_cert)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Permission {
+    ReadOnly,
+    UploadOnly,
+    #[default]
+    Full,
+}
+
+impl Permission {
+    fn to_dav_permission(self) -> dav::Permission {
+        match self {
+            Permission::ReadOnly => dav::Permission::ReadOnly,
+            Permission::UploadOnly => dav::Permission::UploadOnly,
+            Permission::Full => dav::Permission::Full,
+        }
+    }
+}
+
+impl UserConfig {
+    /// Replace a newly typed password by its hash, or keep the hash `previous`
+    /// had for the same user if no password was typed.
+    fn hash_password(&mut self, previous: &[UserConfig]) -> Result<(), String> {
+        match self.password.take().filter(|p| !p.is_empty()) {
+            Some(password) => {
+                self.password_hash =
+                    Some(dav::hash_password(&password).map_err(|e| e.to_string())?);
+            }
+            None if self.password_hash.is_none() => {
+                self.password_hash = previous
+                    .iter()
+                    .find(|u| u.user == self.user)
+                    .and_then(|u| u.password_hash.clone());
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn to_dav_account(&self) -> Result<dav::Account, String> {
+        let account = match (&self.password_hash, &self.client_cert) {
+            (Some(hash), client_cert) => {
+                let account = dav::Account::from_hash(self.user.clone(), hash.clone())
+                    .map_err(|e| e.to_string())?;
+                match client_cert {
+                    Some(name) => account.client_cert(name.clone()),
+                    None => account,
+                }
+            }
+            (None, Some(name)) => dav::Account::client_cert_only(self.user.clone(), name.clone()),
+            (None, None) => return Err(format!("Password of user {} is not set", self.user)),
+        }
+        .permission(self.permission.to_dav_permission());
+        Ok(match &self.root {
+            Some(root) => account.root(root.clone()),
+            None => account,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MountConfig {
+    /// A single segment like `/projects/`.
+    path: String,
+    root: String,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    access: MountAccess,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum MountAccess {
+    #[default]
+    Login,
+    AnonymousRead,
+    Public,
+}
+
+impl MountConfig {
+    fn to_dav_mount(&self) -> dav::Mount {
+        let access = match self.access {
+            MountAccess::Login => dav::MountAccess::Login,
+            MountAccess::AnonymousRead => dav::MountAccess::AnonymousRead,
+            MountAccess::Public => dav::MountAccess::Public,
+        };
+        dav::Mount::new(self.path.clone(), self.root.clone())
+            .read_only(self.read_only)
+            .access(access)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListenerConfig {
+    ip: Option<String>,
+    port: Option<u16>,
+    /// Falls back to `Config::enable_tls` when not set.
+    enable_tls: Option<bool>,
+}
+
+impl ListenerConfig {
+    fn tls(&self, default_tls: bool) -> bool {
+        self.enable_tls.unwrap_or(default_tls)
+    }
+
+    fn to_dav_listener(&self, default_tls: bool) -> Result<dav::Listener, String> {
+        let ip: IpAddr = self
+            .ip
+            .as_ref()
+            .map(|s| {
+                s.parse::<IpAddr>()
+                    .map_err(|e| format!("Invaliable Ip Address: {}", e))
+            })
+            .unwrap_or(Ok(Ipv6Addr::UNSPECIFIED.into()))?;
+        let tls = self.tls(default_tls);
+        let port = self.port.unwrap_or(if tls { 443 } else { 80 });
+        Ok(dav::Listener::new((ip, port).into()).tls(tls))
+    }
+}
+
+#[derive(Clone, Serialize)]
 enum TlsCert {
     #[serde(skip_serializing)]
     Cert {
@@ -103,6 +485,25 @@ enum TlsCert {
     },
 }
 
+impl std::fmt::Debug for TlsCert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsCert::Cert { cert, .. } => f
+                .debug_struct("Cert")
+                .field("cert", &String::from_utf8_lossy(cert))
+                .field("key", &"<redacted>")
+                .finish(),
+            TlsCert::CertFile { cert, key } => f
+                .debug_struct("CertFile")
+                .field("cert", cert)
+                .field("key", key)
+                .finish(),
+        }
+    }
+}
+
+const CERT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
 impl TlsCert {
     fn use_app_default_path() -> Self {
         let (cert_path, key_path) = cert_key_path().expect("Failed to get cert/key path");
@@ -112,6 +513,17 @@ impl TlsCert {
         }
     }
 
+    fn to_dav_tls_config(&self) -> TlsConfig {
+        match self {
+            TlsCert::Cert { cert, key } => TlsConfig::pem(cert.clone(), key.clone()),
+            // the files are watched so rotated certificates are picked up
+            // without restarting the server
+            TlsCert::CertFile { cert, key } => {
+                TlsConfig::pem_file(cert, key).watch(CERT_WATCH_INTERVAL)
+            }
+        }
+    }
+
     fn check_cert_available(&self) -> Result<(), String> {
         match self {
             TlsCert::Cert { cert, key } => {
@@ -136,7 +548,44 @@ impl TlsCert {
 
 #[derive(Debug)]
 struct ServerHandler {
-    handle: tokio::task::JoinHandle<()>,
+    handle: tokio::task::JoinHandle<anyhow::Result<()>>,
+    server: Arc<dav::DavServer>,
+    shutdown: dav::ShutdownHandle,
+    /// Bound addresses and start time, set once the listeners are bound.
+    running: Arc<Mutex<Option<(Vec<SocketAddr>, SystemTime)>>>,
+    /// How the server task ended, taken from `handle` once it has finished.
+    exit: Option<Result<(), String>>,
+}
+
+impl ServerHandler {
+    fn status(&mut self) -> DavServerStatus {
+        if self.exit.is_none() && self.handle.is_finished() {
+            self.exit = (&mut self.handle)
+                .now_or_never()
+                .map(|result| match result {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(err)) => Err(format!("{:#}", err)),
+                    Err(err) => Err(err.to_string()),
+                });
+        }
+
+        match &self.exit {
+            Some(Ok(())) => DavServerStatus::Stopped,
+            Some(Err(error)) => DavServerStatus::Failed {
+                error: error.clone(),
+            },
+            None => match &*self.running.lock() {
+                Some((addrs, since)) => DavServerStatus::Running {
+                    addrs: addrs.clone(),
+                    since: since
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64,
+                },
+                None => DavServerStatus::Starting,
+            },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -144,6 +593,7 @@ struct State {
     tokio_runtime: Mutex<Option<tokio::runtime::Runtime>>,
     config: Mutex<Config>,
     server_handler: Mutex<Option<ServerHandler>>,
+    logging: Logging,
 }
 
 #[tauri::command]
@@ -159,7 +609,7 @@ fn get_config(state: tauri::State<State>) -> Config {
                     Ok(())
                 })
                 .or_else(|e| {
-                    eprintln!("Failed to get cert/key path: {}", e);
+                    tracing::error!("Failed to get cert/key path: {}", e);
                     Err(())
                 })
                 .unwrap_or(());
@@ -169,10 +619,16 @@ fn get_config(state: tauri::State<State>) -> Config {
 
 #[tauri::command]
 fn update_config(state: tauri::State<State>, mut config: Config) -> Result<(), String> {
+    let mut config_guard = state.config.lock();
+    for user in &mut config.users {
+        user.hash_password(&config_guard.users)?;
+    }
     config.validate()?;
+    state.logging.apply(&config.log)?;
 
-    let mut config_guard = state.config.lock();
     config.tls_cert = config_guard.tls_cert.take();
+    config.share_key = config_guard.share_key.take();
+    config.shares = std::mem::take(&mut config_guard.shares);
     *config_guard = config;
     Ok(())
 }
@@ -209,51 +665,38 @@ fn import_tls_or_cert_from_path(
     Ok(())
 }
 
+/// Wait until the server has bound its listeners and return the bound
+/// addresses, or the error that kept it from starting.
 #[tauri::command]
-fn start_dav_server(state: tauri::State<State>) -> Result<(), String> {
-    let mut handler_guard = state.server_handler.lock();
-    if let Some(handler) = handler_guard.take() {
-        handler.handle.abort();
-    }
+async fn start_dav_server(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, State>,
+) -> Result<Vec<SocketAddr>, String> {
+    let bound = spawn_dav_server(&app, &state)?;
+    bound
+        .await
+        .map_err(|_| "dav server exited before binding".to_string())?
+}
 
+fn spawn_dav_server(
+    app: &tauri::AppHandle,
+    state: &State,
+) -> Result<oneshot::Receiver<Result<Vec<SocketAddr>, String>>, String> {
     let config = state.config.lock().clone();
-    println!("config: {:?}", config);
-    let bind_ip: IpAddr = config
-        .ip
-        .map(|s| {
-            s.parse::<IpAddr>()
-                .map_err(|e| format!("Invaliable Ip Address: {}", e))
-        })
-        .unwrap_or(Ok(Ipv6Addr::UNSPECIFIED.into()))?;
-    let bind_port = config
-        .port
-        .unwrap_or_else(|| if config.enable_tls { 443 } else { 80 });
-    let bind = (bind_ip, bind_port).into();
+    tracing::debug!("config: {:?}", config);
+    // keep the running server if the new one could not start anyway
+    config.validate()?;
+    let dav_server = build_dav_config(&config)?;
+    dav_server.validate().map_err(|err| format!("{:#}", err))?;
 
-    let mut dav_server = dav::DavServer::builder()
-        .bind(bind)
-        .root(config.root.clone().expect("Root path is not set"));
-    if let Some((user, password)) = &config.auth {
-        dav_server = dav_server.authorization(user.clone(), password.clone());
-    }
-    if config.enable_tls {
-        match &config
-            .tls_cert
-            .unwrap_or_else(TlsCert::use_app_default_path)
-        {
-            TlsCert::Cert { cert, key } => {
-                dav_server = dav_server.tls(TlsConfig::pem(cert.clone(), key.clone()));
-            }
-            TlsCert::CertFile { cert, key } => {
-                let cert_bytes = std::fs::read(cert).map_err(|e| e.to_string())?;
-                let key_bytes = std::fs::read(key).map_err(|e| e.to_string())?;
-                dav_server = dav_server.tls(TlsConfig::pem(cert_bytes, key_bytes));
-            }
-        }
-    }
+    let mut handler_guard = state.server_handler.lock();
+    let previous = handler_guard.take().map(|handler| {
+        handler.shutdown.shutdown();
+        handler.shutdown
+    });
 
-    println!("dav_server: {:?}", dav_server);
-    let dav_server = dav_server.build();
+    tracing::debug!("dav_server: {:?}", dav_server);
+    let dav_server = Arc::new(dav_server.build());
 
     let mut rt_guard = state.tokio_runtime.lock();
     let rt = rt_guard.get_or_insert({
@@ -271,45 +714,539 @@ fn start_dav_server(state: tauri::State<State>) -> Result<(), String> {
 
     // let _ = rt.enter();
 
-    let handle = rt.spawn(async move {
-        if let Err(err) = dav_server.run().await {
-            println!("dav server error: {}", err);
+    // ends when the server is replaced and its event sender dropped
+    let mut events = dav_server.subscribe();
+    let app = app.clone();
+    rt.spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(event) = DavEvent::from_dav(event) {
+                        let _ = app.emit_all("dav-event", event);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
         }
     });
-    handler_guard.replace(ServerHandler { handle });
 
+    let shutdown = dav_server.shutdown_handle();
+    let server = dav_server.clone();
+    let running = Arc::new(Mutex::new(None));
+    let (bound_tx, bound_rx) = oneshot::channel();
+    let handle = rt.spawn({
+        let running = running.clone();
+        async move {
+            // wait for the previous server to release its port
+            if let Some(previous) = previous {
+                previous.stopped().await;
+            }
+            let server = match dav_server.bind().await {
+                Ok(server) => {
+                    let addrs = server.local_addrs();
+                    *running.lock() = Some((addrs.clone(), SystemTime::now()));
+                    let _ = bound_tx.send(Ok(addrs));
+                    server
+                }
+                Err(err) => {
+                    tracing::error!("dav server error: {:#}", err);
+                    let _ = bound_tx.send(Err(format!("{:#}", err)));
+                    return Err(err);
+                }
+            };
+            server.serve().await.map_err(|err| {
+                tracing::error!("dav server error: {:#}", err);
+                err
+            })
+        }
+    });
+    handler_guard.replace(ServerHandler {
+        handle,
+        server,
+        shutdown,
+        running,
+        exit: None,
+    });
+
+    Ok(bound_rx)
+}
+
+fn build_dav_config(config: &Config) -> Result<dav::DavConfig, String> {
+    let listener_configs = if config.listeners.is_empty() {
+        vec![ListenerConfig::default()]
+    } else {
+        config.listeners.clone()
+    };
+    let listeners = listener_configs
+        .iter()
+        .map(|l| l.to_dav_listener(config.enable_tls))
+        .collect::<Result<Vec<_>, _>>()?;
+    // the https redirect listens on the addresses the server is reachable at
+    let mut listener_ips = listeners.iter().map(|l| l.bind().ip()).collect::<Vec<_>>();
+    if listener_ips.iter().any(IpAddr::is_unspecified) {
+        listener_ips = vec![Ipv6Addr::UNSPECIFIED.into()];
+    }
+    listener_ips.sort();
+    listener_ips.dedup();
+
+    let accounts = config
+        .users
+        .iter()
+        .map(UserConfig::to_dav_account)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut dav_server = dav::DavServer::builder()
+        .listeners(listeners)
+        .accounts(accounts)
+        .ip_filter(config.ip_filter.to_dav_ip_filter()?)
+        .read_only(config.read_only)
+        .mounts(
+            config
+                .mounts
+                .iter()
+                .map(MountConfig::to_dav_mount)
+                .collect(),
+        );
+    if let Some(htpasswd) = &config.htpasswd_file {
+        dav_server = dav_server.htpasswd(htpasswd);
+    }
+    let config_dir = tauri::api::path::config_dir().ok_or("Failed to get config dir")?;
+    // only used for folders without extended attributes, e.g. on Windows
+    dav_server = dav_server.property_file(config_dir.join("properties.redb"));
+    if config.persist_locks {
+        dav_server = dav_server.lock_file(config_dir.join("locks.json"));
+    }
+    if let Some(root) = &config.root {
+        dav_server = dav_server
+            .root(root.clone())
+            .anonymous_read(config.anonymous_read);
+        if let Some(key) = &config.share_key {
+            dav_server = dav_server
+                .share_key(dav::ShareKey::new(key.clone()))
+                .share_use_file(config_dir.join("share_uses.json"))
+                .revoked_shares(
+                    config
+                        .shares
+                        .iter()
+                        .filter(|share| share.revoked)
+                        .map(|share| share.id.clone())
+                        .collect(),
+                );
+        }
+    }
+    dav_server = match config.lockout {
+        LockoutConfig { threshold: 0, .. } => dav_server.no_lockout(),
+        LockoutConfig {
+            threshold,
+            ban_secs,
+        } => dav_server.lockout(dav::lockout::LockoutPolicy {
+            threshold,
+            ban_duration: Duration::from_secs(ban_secs),
+        }),
+    };
+    if listener_configs.iter().any(|l| l.tls(config.enable_tls)) {
+        dav_server = dav_server.tls(build_tls_config(config));
+
+        if let Some(port) = config.https_redirect_port {
+            for &ip in &listener_ips {
+                dav_server = dav_server.add_https_redirect((ip, port).into());
+            }
+        }
+    }
+
+    Ok(dav_server)
+}
+
+fn build_tls_config(config: &Config) -> TlsConfig {
+    let tls_config = config
+        .tls_cert
+        .clone()
+        .unwrap_or_else(TlsCert::use_app_default_path)
+        .to_dav_tls_config();
+    match &config.client_ca_file {
+        Some(ca) => tls_config
+            .client_ca_file(ca)
+            .require_client_cert(config.require_client_cert),
+        None => tls_config,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum ReloadStatus {
+    Applied,
+    RebindRequired,
+}
+
+/// Apply the current config to the running server without dropping its
+/// listeners. Changes to the listeners or TLS need `start_dav_server` and are
+/// reported as `RebindRequired`.
+#[tauri::command]
+fn reload_dav_server(state: tauri::State<State>) -> Result<ReloadStatus, String> {
+    let config = state.config.lock().clone();
+    let dav_config = build_dav_config(&config)?;
+
+    let handler_guard = state.server_handler.lock();
+    let handler = handler_guard
+        .as_ref()
+        .filter(|handler| !handler.handle.is_finished())
+        .ok_or("dav server is not running")?;
+    match handler
+        .server
+        .reload(dav_config)
+        .map_err(|e| format!("{:#}", e))?
+    {
+        dav::ReloadStatus::Applied => Ok(ReloadStatus::Applied),
+        dav::ReloadStatus::RebindRequired => Ok(ReloadStatus::RebindRequired),
+    }
+}
+
+/// Load the configured certificate into the running server. If it cannot be
+/// loaded the server keeps using the previous one.
+#[tauri::command]
+async fn reload_tls_cert(state: tauri::State<'_, State>) -> Result<(), String> {
+    let tls_config = build_tls_config(&state.config.lock());
+    let server = state
+        .server_handler
+        .lock()
+        .as_ref()
+        .filter(|handler| !handler.handle.is_finished())
+        .map(|handler| handler.server.clone())
+        .ok_or("dav server is not running")?;
+    server
+        .reload_tls(tls_config)
+        .await
+        .map_err(|e| format!("{:#}", e))
+}
+
+/// Payload of the `dav-event` event emitted to the frontend.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum DavEvent {
+    Connected {
+        client: SocketAddr,
+    },
+    AuthFailed {
+        client: Option<SocketAddr>,
+        user: Option<String>,
+    },
+    Uploaded {
+        user: Option<String>,
+        path: String,
+    },
+    Deleted {
+        user: Option<String>,
+        path: String,
+    },
+    Moved {
+        user: Option<String>,
+        from: String,
+        to: String,
+    },
+    Copied {
+        user: Option<String>,
+        from: String,
+        to: String,
+    },
+    CollectionCreated {
+        user: Option<String>,
+        path: String,
+    },
+    Locked {
+        user: Option<String>,
+        path: String,
+    },
+    Unlocked {
+        user: Option<String>,
+        path: String,
+    },
+}
+
+impl DavEvent {
+    fn from_dav(event: dav::events::DavEvent) -> Option<Self> {
+        use dav::events::DavEvent as E;
+        Some(match event {
+            E::Connected { client } => DavEvent::Connected { client },
+            E::AuthFailed { client, user } => DavEvent::AuthFailed { client, user },
+            E::Uploaded { user, path } => DavEvent::Uploaded { user, path },
+            E::Deleted { user, path } => DavEvent::Deleted { user, path },
+            E::Moved { user, from, to } => DavEvent::Moved { user, from, to },
+            E::Copied { user, from, to } => DavEvent::Copied { user, from, to },
+            E::CollectionCreated { user, path } => DavEvent::CollectionCreated { user, path },
+            E::Locked { user, path } => DavEvent::Locked { user, path },
+            E::Unlocked { user, path } => DavEvent::Unlocked { user, path },
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Metrics {
+    requests: Vec<RequestCount>,
+    latency: Vec<LatencyHistogram>,
+    bytes_in: u64,
+    bytes_out: u64,
+    active_connections: i64,
+    auth_failures: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestCount {
+    method: String,
+    status: u16,
+    count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LatencyHistogram {
+    method: String,
+    buckets: Vec<(f64, u64)>,
+    sum: f64,
+    count: u64,
+}
+
+impl From<dav::metrics::MetricsSnapshot> for Metrics {
+    fn from(snapshot: dav::metrics::MetricsSnapshot) -> Self {
+        Metrics {
+            requests: snapshot
+                .requests
+                .into_iter()
+                .map(|r| RequestCount {
+                    method: r.method,
+                    status: r.status,
+                    count: r.count,
+                })
+                .collect(),
+            latency: snapshot
+                .latency
+                .into_iter()
+                .map(|h| LatencyHistogram {
+                    method: h.method,
+                    buckets: h.buckets,
+                    sum: h.sum,
+                    count: h.count,
+                })
+                .collect(),
+            bytes_in: snapshot.bytes_in,
+            bytes_out: snapshot.bytes_out,
+            active_connections: snapshot.active_connections,
+            auth_failures: snapshot.auth_failures,
+        }
+    }
+}
+
+/// Counters of the current server, or of the last one if it has stopped.
+#[tauri::command]
+fn get_metrics(state: tauri::State<State>) -> Result<Metrics, String> {
+    let handler_guard = state.server_handler.lock();
+    let handler = handler_guard.as_ref().ok_or("dav server is not running")?;
+    Ok(handler.server.metrics().snapshot().into())
+}
+
+/// What failed logins are counted for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "camelCase")]
+enum BanTarget {
+    Ip(IpAddr),
+    User(String),
+}
+
+impl BanTarget {
+    fn from_dav(target: dav::lockout::BanTarget) -> Self {
+        match target {
+            dav::lockout::BanTarget::Ip(ip) => BanTarget::Ip(ip),
+            dav::lockout::BanTarget::User(user) => BanTarget::User(user),
+        }
+    }
+
+    fn to_dav_ban_target(&self) -> dav::lockout::BanTarget {
+        match self {
+            BanTarget::Ip(ip) => dav::lockout::BanTarget::Ip(*ip),
+            BanTarget::User(user) => dav::lockout::BanTarget::User(user.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Ban {
+    target: BanTarget,
+    failures: u32,
+    /// Milliseconds since the Unix epoch.
+    until: u64,
+}
+
+#[tauri::command]
+fn get_bans(state: tauri::State<State>) -> Result<Vec<Ban>, String> {
+    let handler_guard = state.server_handler.lock();
+    let handler = handler_guard.as_ref().ok_or("dav server is not running")?;
+    Ok(handler
+        .server
+        .bans()
+        .into_iter()
+        .map(|ban| Ban {
+            target: BanTarget::from_dav(ban.target),
+            failures: ban.failures,
+            until: ban
+                .until
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[tauri::command]
+fn clear_ban(state: tauri::State<State>, target: BanTarget) -> Result<(), String> {
+    let handler_guard = state.server_handler.lock();
+    let handler = handler_guard.as_ref().ok_or("dav server is not running")?;
+    handler.server.clear_ban(&target.to_dav_ban_target());
+    Ok(())
+}
+
+#[tauri::command]
+fn clear_bans(state: tauri::State<State>) -> Result<(), String> {
+    let handler_guard = state.server_handler.lock();
+    let handler = handler_guard.as_ref().ok_or("dav server is not running")?;
+    handler.server.clear_bans();
+    Ok(())
+}
+
+/// Apply the shares of `config` to the running server, if there is one.
+/// Apply the shares of `config` to the running server. Fails if the server
+/// runs with listener or TLS settings `config` changes, as the shares would
+/// only take effect after a restart; the caller keeps its config then.
+fn reload_shares(state: &State, config: &Config) -> Result<(), String> {
+    let handler_guard = state.server_handler.lock();
+    if let Some(handler) = handler_guard
+        .as_ref()
+        .filter(|handler| !handler.handle.is_finished())
+    {
+        match handler
+            .server
+            .reload(build_dav_config(config)?)
+            .map_err(|e| format!("{:#}", e))?
+        {
+            dav::ReloadStatus::Applied => {}
+            dav::ReloadStatus::RebindRequired => {
+                return Err(
+                    "Restart the server to apply the pending settings before changing shares"
+                        .to_string(),
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_shares(state: tauri::State<State>) -> Vec<ShareConfig> {
+    let shares = state.config.lock().shares.clone();
+    let handler_guard = state.server_handler.lock();
+    shares
+        .into_iter()
+        .map(|mut share| {
+            if let Some(handler) = handler_guard.as_ref() {
+                share.uses = handler.server.share_uses(&share.id);
+            }
+            share
+        })
+        .collect()
+}
+
+/// Create a share of `path` below the root and return it with the token to
+/// hand out. `expires` is in milliseconds since the Unix epoch.
+#[tauri::command]
+fn create_share(
+    state: tauri::State<State>,
+    path: String,
+    expires: u64,
+    scope: ShareScope,
+    max_uses: Option<u32>,
+) -> Result<ShareConfig, String> {
+    let mut config = state.config.lock().clone();
+    if config.root.is_none() {
+        return Err("Shares need a root path".to_string());
+    }
+    let key = config
+        .share_key
+        .get_or_insert_with(|| dav::ShareKey::generate().as_bytes().to_vec());
+    let mut share = dav::Share::new(
+        path,
+        SystemTime::UNIX_EPOCH + Duration::from_millis(expires),
+        scope.to_dav_share_scope(),
+    );
+    share.max_uses = max_uses;
+    let share = ShareConfig {
+        token: dav::ShareKey::new(key.clone()).mint(&share),
+        id: share.id,
+        path: share.path,
+        expires,
+        scope,
+        max_uses,
+        revoked: false,
+        uses: 0,
+    };
+    config.shares.push(share.clone());
+
+    reload_shares(&state, &config)?;
+    *state.config.lock() = config;
+    Ok(share)
+}
+
+#[tauri::command]
+fn revoke_share(state: tauri::State<State>, id: String) -> Result<(), String> {
+    let mut config = state.config.lock().clone();
+    let share = config
+        .shares
+        .iter_mut()
+        .find(|share| share.id == id)
+        .ok_or("share not found")?;
+    share.revoked = true;
+
+    reload_shares(&state, &config)?;
+    *state.config.lock() = config;
     Ok(())
 }
 
 #[tauri::command]
 fn stop_dav_server(state: tauri::State<State>) -> Result<(), String> {
-    let mut handler_guard = state.server_handler.lock();
-    if let Some(handler) = handler_guard.take() {
-        handler.handle.abort();
+    let handler_guard = state.server_handler.lock();
+    if let Some(handler) = handler_guard.as_ref() {
+        // keep the handler around so `check_dav_server` reports `Running`
+        // until the in-flight requests have drained
+        handler.shutdown.shutdown();
     }
     Ok(())
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(tag = "status", rename_all = "camelCase")]
 #[non_exhaustive]
 enum DavServerStatus {
-    Running,
+    /// Waiting for the previous server to stop or binding the listeners.
+    Starting,
+    Running {
+        addrs: Vec<SocketAddr>,
+        /// Milliseconds since the Unix epoch.
+        since: u64,
+    },
     Stopped,
+    /// The server failed to start or stopped with an error; `error` is the
+    /// whole error chain.
+    Failed {
+        error: String,
+    },
 }
 
 #[tauri::command]
 fn check_dav_server(state: tauri::State<State>) -> Result<DavServerStatus, String> {
-    let handler_guard = state.server_handler.lock();
-    match handler_guard.as_ref() {
-        Some(hander) => {
-            if hander.handle.is_finished() {
-                println!("dav server is stopped");
-                Ok(DavServerStatus::Stopped)
-            } else {
-                Ok(DavServerStatus::Running)
-            }
-        }
+    let mut handler_guard = state.server_handler.lock();
+    match handler_guard.as_mut() {
+        Some(handler) => Ok(handler.status()),
         None => Ok(DavServerStatus::Stopped),
     }
 }
@@ -320,6 +1257,7 @@ fn main() {
         tokio_runtime: Mutex::new(None),
         config: Mutex::new(config),
         server_handler: Mutex::new(None),
+        logging: Logging::init(),
     };
     tauri::Builder::default()
         .setup(|app| {
@@ -337,7 +1275,7 @@ fn main() {
                 use window_vibrancy::apply_mica;
                 let r = apply_mica(&main, None);
                 if let Err(err) = r {
-                    eprintln!("Failed to apply mica: {:?}", err);
+                    tracing::warn!("Failed to apply mica: {:?}", err);
                 };
             }
             Ok(())
@@ -347,6 +1285,15 @@ fn main() {
             update_config,
             import_tls_or_cert_from_path,
             start_dav_server,
+            reload_dav_server,
+            reload_tls_cert,
+            get_metrics,
+            get_bans,
+            clear_ban,
+            clear_bans,
+            list_shares,
+            create_share,
+            revoke_share,
             stop_dav_server,
             check_dav_server,
         ])
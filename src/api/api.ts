@@ -1,12 +1,94 @@
 import { invoke } from "@tauri-apps/api";
+import { UnlistenFn, listen } from "@tauri-apps/api/event";
 
-export type Config = {
+export type ListenerConfig = {
   ip?: string;
   port?: number;
+  /** falls back to `Config.enableTls` when unset */
+  enableTls?: boolean;
+};
+
+export type LogConfig = {
+  /** tracing filter, e.g. `info` or `dav::access=debug`; defaults to `info` */
+  level?: string;
+  /** also append the log to this file */
+  file?: string;
+  /** write the log file as JSON lines */
+  json?: boolean;
+};
+
+export type UserConfig = {
+  user: string;
+  /** a new password, hashed by the app; it is never sent back */
+  password?: string;
+  /** argon2 or bcrypt hash of the current password */
+  passwordHash?: string;
+  /** serve this user from its own folder instead of `Config.root` */
+  root?: string;
+  /** `full` if not set */
+  permission?: Permission;
+  /** subject or alternative name of a client certificate that logs in as
+   * this user, with or without a password */
+  clientCert?: string;
+};
+
+export type Permission = "readOnly" | "uploadOnly" | "full";
+
+export type MountConfig = {
+  /** a single segment like `/projects/` */
+  path: string;
+  root: string;
+  readOnly?: boolean;
+  /** `login` if not set */
+  access?: MountAccess;
+};
+
+/** `login` lets the users in, `anonymousRead` everyone read and the users
+ * write, `public` everyone */
+export type MountAccess = "login" | "anonymousRead" | "public";
+
+export type LockoutConfig = {
+  /** failed logins before an address or user is banned; 0 disables */
+  threshold: number;
+  banSecs: number;
+};
+
+export type IpFilterConfig = {
+  /** only accept loopback and private network addresses */
+  privateLanOnly?: boolean;
+  /** CIDR ranges to accept, e.g. `10.0.0.0/8`; with `privateLanOnly` in
+   * addition to the private ones */
+  allow?: string[];
+  /** CIDR ranges to refuse */
+  deny?: string[];
+};
+
+export type Config = {
+  listeners?: ListenerConfig[];
   root?: string;
 
-  auth?: [user: string, password: string];
+  users?: UserConfig[];
+  /** Apache htpasswd file whose users may log in as well; it is re-read
+   * when it changes */
+  htpasswdFile?: string;
+  /** everyone may browse and download, only users may make changes */
+  anonymousRead?: boolean;
+  /** nobody may change files */
+  readOnly?: boolean;
+  /** further folders served at paths of their own; `root` must be unset */
+  mounts?: MountConfig[];
+  /** keep WebDAV locks across restarts of the server */
+  persistLocks?: boolean;
   enableTls?: boolean;
+  /** plain HTTP port redirecting to the TLS listener */
+  httpsRedirectPort?: number;
+  /** PEM file of the CAs that client certificates are verified against */
+  clientCaFile?: string;
+  /** refuse TLS clients without a client certificate */
+  requireClientCert?: boolean;
+  log?: LogConfig;
+  lockout?: LockoutConfig;
+  ipFilter?: IpFilterConfig;
 };
 
 export async function getConfig(): Promise<Config> {
@@ -29,18 +111,137 @@ export async function updateCertConfig(config: CertConfig): Promise<void> {
   return await invoke("import_tls_or_cert_from_path", config);
 }
 
-export async function startDavServer(): Promise<void> {
+/** Resolves with the addresses the server is bound to. */
+export async function startDavServer(): Promise<string[]> {
   return await invoke("start_dav_server");
 }
 
+export enum ReloadStatus {
+  Applied = "applied",
+  RebindRequired = "rebindRequired",
+}
+
+/** Apply the saved config to the running server without restarting it. */
+export async function reloadDavServer(): Promise<ReloadStatus> {
+  return await invoke("reload_dav_server");
+}
+
+/** Load the configured TLS certificate into the running server. */
+export async function reloadTlsCert(): Promise<void> {
+  return await invoke("reload_tls_cert");
+}
+
+export type DavEvent =
+  | { type: "connected"; client: string }
+  | { type: "authFailed"; client?: string; user?: string }
+  | { type: "uploaded"; user?: string; path: string }
+  | { type: "deleted"; user?: string; path: string }
+  | { type: "moved"; user?: string; from: string; to: string }
+  | { type: "copied"; user?: string; from: string; to: string }
+  | { type: "collectionCreated"; user?: string; path: string }
+  | { type: "locked"; user?: string; path: string }
+  | { type: "unlocked"; user?: string; path: string };
+
+/** Call `handler` for every activity on the running server. */
+export async function listenDavEvents(
+  handler: (event: DavEvent) => void
+): Promise<UnlistenFn> {
+  return await listen<DavEvent>("dav-event", (event) => handler(event.payload));
+}
+
+export type Metrics = {
+  requests: { method: string; status: number; count: number }[];
+  /** buckets are `[upper bound in seconds, cumulative count]` pairs */
+  latency: {
+    method: string;
+    buckets: [number, number][];
+    sum: number;
+    count: number;
+  }[];
+  bytesIn: number;
+  bytesOut: number;
+  activeConnections: number;
+  authFailures: number;
+};
+
+export async function getMetrics(): Promise<Metrics> {
+  return await invoke("get_metrics");
+}
+
+export type BanTarget =
+  | { kind: "ip"; value: string }
+  | { kind: "user"; value: string };
+
+export type Ban = {
+  target: BanTarget;
+  failures: number;
+  /** milliseconds since the Unix epoch */
+  until: number;
+};
+
+/** Addresses and users banned for failing to log in too often. */
+export async function getBans(): Promise<Ban[]> {
+  return await invoke("get_bans");
+}
+
+export async function clearBan(target: BanTarget): Promise<void> {
+  return await invoke("clear_ban", { target });
+}
+
+export async function clearBans(): Promise<void> {
+  return await invoke("clear_bans");
+}
+
+export type ShareScope = "read" | "write";
+
+export type Share = {
+  id: string;
+  /** folder below `Config.root` */
+  path: string;
+  /** milliseconds since the Unix epoch */
+  expires: number;
+  scope: ShareScope;
+  maxUses?: number;
+  /** sent as `Bearer` token or in the path of `sharePath(token)` */
+  token: string;
+  revoked: boolean;
+  /** files transferred with the token so far */
+  uses: number;
+};
+
+/** Path of a link that logs in with `token`. */
+export function sharePath(token: string): string {
+  return `/_share/${token}/`;
+}
+
+export async function listShares(): Promise<Share[]> {
+  return await invoke("list_shares");
+}
+
+export async function createShare(
+  path: string,
+  expires: number,
+  scope: ShareScope,
+  maxUses?: number
+): Promise<Share> {
+  return await invoke("create_share", { path, expires, scope, maxUses });
+}
+
+export async function revokeShare(id: string): Promise<void> {
+  return await invoke("revoke_share", { id });
+}
+
 export async function stopDavServer(): Promise<void> {
   return await invoke("stop_dav_server");
 }
 
-export enum DavServerStatus {
-  Stopped = "stopped",
-  Running = "running",
-}
+export type DavServerStatus =
+  | { status: "starting" }
+  /** `since` is in milliseconds since the Unix epoch */
+  | { status: "running"; addrs: string[]; since: number }
+  | { status: "stopped" }
+  /** `error` is the whole error chain */
+  | { status: "failed"; error: string };
 
 export async function checkDavServer(): Promise<DavServerStatus> {
   return await invoke("check_dav_server");
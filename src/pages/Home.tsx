@@ -3,6 +3,8 @@ import React, { useEffect, useState } from "react";
 import {
   Option,
   Combobox,
+  Dropdown,
+  Checkbox,
   Field,
   Input,
   makeStyles,
@@ -17,14 +19,34 @@ import {
   DialogContent,
   Spinner,
 } from "@fluentui/react-components";
-import { EyeRegular, EyeOffRegular } from "@fluentui/react-icons";
+import {
+  AddRegular,
+  DismissRegular,
+  EyeRegular,
+  EyeOffRegular,
+} from "@fluentui/react-icons";
 
 import { dialog } from "@tauri-apps/api";
 
 import { useGlobalState } from "../store/state";
 import {
-  DavServerStatus,
+  ListenerConfig,
+  UserConfig,
+  Permission,
+  MountConfig,
+  MountAccess,
+  Ban,
+  ReloadStatus,
+  Share,
+  ShareScope,
+  clearBan,
+  getBans,
+  createShare,
+  listShares,
+  revokeShare,
+  sharePath,
   checkDavServer,
+  reloadDavServer,
   startDavServer,
   stopDavServer,
   updateConfig,
@@ -61,6 +83,23 @@ const useStyles = makeStyles({
   },
 });
 
+const permissionLabels: Record<Permission, string> = {
+  readOnly: "read only",
+  uploadOnly: "upload only",
+  full: "full",
+};
+
+const mountAccessLabels: Record<MountAccess, string> = {
+  login: "login",
+  anonymousRead: "guests read",
+  public: "public",
+};
+
+const shareScopeLabels: Record<ShareScope, string> = {
+  read: "read",
+  write: "read & write",
+};
+
 export default function Home() {
   const classes = useStyles();
 
@@ -69,33 +108,61 @@ export default function Home() {
   const setConfig = useGlobalState((state) => state.setConfig);
 
   // auth states
-  const [userLocal, setUserLocal] = useState("");
-  const [passwordLocal, setPasswordLocal] = useState("");
+  const [users, setUsers] = useState<UserConfig[]>([
+    { user: "", password: "" },
+  ]);
   const [showPassword, setShowPassword] = useState(false);
   const [enableAuth, setEnableAuth] = useState(false);
+  const [htpasswdFile, setHtpasswdFile] = useState<string | undefined>();
+  const completeUsers = users.filter(
+    (u) => u.user && (u.password || u.passwordHash || u.clientCert)
+  );
   useEffect(() => {
-    if (enableAuth) {
-      setConfig((config) => ({ ...config, auth: [userLocal, passwordLocal] }));
-    } else {
-      setConfig((config) => ({
-        ...config,
-        auth: undefined,
-      }));
-    }
-  }, [enableAuth, userLocal, passwordLocal, setConfig]);
+    setConfig((config) => ({
+      ...config,
+      users: enableAuth
+        ? users.filter(
+            (u) => u.user && (u.password || u.passwordHash || u.clientCert)
+          )
+        : [],
+      htpasswdFile: enableAuth ? htpasswdFile : undefined,
+    }));
+  }, [enableAuth, users, htpasswdFile, setConfig]);
+  const setUser = (index: number, user: Partial<UserConfig>) =>
+    setUsers(users.map((u, i) => (i === index ? { ...u, ...user } : u)));
+  const addUser = () => setUsers([...users, { user: "", password: "" }]);
+  const removeUser = (index: number) =>
+    setUsers(users.filter((_, i) => i !== index));
 
   // server states
   const [running, setRunning] = useState(false);
+  const [failure, setFailure] = useState<string | null>(null);
   const [processing, setProcessing] = useState(false);
+  const [bans, setBans] = useState<Ban[]>([]);
+
+  // share link states
+  const [shares, setShares] = useState<Share[]>([]);
+  const [sharePathInput, setSharePathInput] = useState("/");
+  const [shareScope, setShareScope] = useState<ShareScope>("read");
+  const [shareDays, setShareDays] = useState(7);
+  const [shareMaxUses, setShareMaxUses] = useState<number | undefined>();
+  useEffect(() => {
+    listShares().then(setShares).catch(console.error);
+  }, []);
 
   useEffect(() => {
     if (!processing) {
       const timer = setInterval(() => {
         checkDavServer().then((res) => {
-          if (res == DavServerStatus.Running) {
-            setRunning(true);
+          setRunning(res.status == "running" || res.status == "starting");
+          setFailure(res.status == "failed" ? res.error : null);
+          if (res.status == "running") {
+            getBans()
+              .then(setBans)
+              .catch(() => setBans([]));
+            listShares().then(setShares).catch(console.error);
           } else {
-            setRunning(false);
+            setBans([]);
           }
         });
       }, 2000);
@@ -106,15 +173,77 @@ export default function Home() {
   // control dialog
   const [dialogDom, setDialogDom] = useState<React.JSX.Element | null>(null);
 
-  const setIp = (ip: string) => setConfig({ ...config, ip });
-  const setPort = (port: number) => setConfig({ ...config, port });
+  const listeners: ListenerConfig[] = config?.listeners?.length
+    ? config.listeners
+    : [{}];
+  const setListener = (index: number, listener: ListenerConfig) =>
+    setConfig({
+      ...config,
+      listeners: listeners.map((l, i) =>
+        i === index ? { ...l, ...listener } : l
+      ),
+    });
+  const addListener = () =>
+    setConfig({ ...config, listeners: [...listeners, {}] });
+  const removeListener = (index: number) =>
+    setConfig({
+      ...config,
+      listeners: listeners.filter((_, i) => i !== index),
+    });
   const setRoot = (root: string) => setConfig({ ...config, root });
 
+  const mounts = config?.mounts ?? [];
+  const setMount = (index: number, mount: Partial<MountConfig>) =>
+    setConfig({
+      ...config,
+      mounts: mounts.map((m, i) => (i === index ? { ...m, ...mount } : m)),
+    });
+  const addMount = () =>
+    setConfig({ ...config, mounts: [...mounts, { path: "", root: "" }] });
+  const removeMount = (index: number) =>
+    setConfig({ ...config, mounts: mounts.filter((_, i) => i !== index) });
+
+  const showError = (err: unknown) =>
+    setDialogDom(
+      <Dialog modalType="alert" open>
+        <DialogSurface>
+          <DialogBody>
+            <DialogTitle>Error</DialogTitle>
+            <DialogContent>{String(err)}</DialogContent>
+            <DialogActions>
+              <Button appearance="primary" onClick={() => setDialogDom(null)}>
+                OK
+              </Button>
+            </DialogActions>
+          </DialogBody>
+        </DialogSurface>
+      </Dialog>
+    );
+
+  // apply config changes to the running server, restarting it only when the
+  // listeners changed
+  const applyConfig = () => {
+    if (processing) return;
+    setProcessing(true);
+    updateConfig(config!)
+      .then(() => reloadDavServer())
+      .then((status) =>
+        status == ReloadStatus.RebindRequired
+          ? startDavServer().then(() => undefined)
+          : undefined
+      )
+      .catch((err) => {
+        console.log(err);
+        showError(err);
+      })
+      .finally(() => setProcessing(false));
+  };
+
   const enableAuthSwitchCb = () => {
     if (enableAuth) {
       setEnableAuth(!enableAuth);
     } else {
-      if (userLocal && passwordLocal) {
+      if (completeUsers.length > 0 || htpasswdFile) {
         setEnableAuth(!enableAuth);
       } else {
         console.log("user and password must set to enable login");
@@ -125,7 +254,8 @@ export default function Home() {
               <DialogBody>
                 <DialogTitle>Warning</DialogTitle>
                 <DialogContent>
-                  User and Password must set to enable login.
+                  User and Password or an htpasswd file must set to enable
+                  login.
                 </DialogContent>
                 <DialogActions>
                   <Button
@@ -147,56 +277,92 @@ export default function Home() {
     <>
       {dialogDom}
 
-      <div className={classes.row}>
-        <div className={classes.item} style={{ flex: 6 }}>
-          <Field label="IP">
-            <Combobox
-              value={config?.ip ?? ""}
-              onChange={(e) => setIp(e.target.value)}
-              onOptionSelect={(_, v) => setIp(v.optionText ?? "")}
-              placeholder="*"
-              appearance="underline"
-              freeform
-            >
-              {["127.0.0.1", "[::]", "0.0.0.0"].map((v) => (
-                <Option key={v} text={v}>
-                  {v}
-                </Option>
-              ))}
-            </Combobox>
-          </Field>
-        </div>
-        <div
-          className={classes.row}
-          style={{
-            flex: 2,
-            alignSelf: "flex-end",
-            justifyContent: "center",
-          }}
-        >
-          <span
-            className={classes.item}
+      {listeners.map((listener, index) => (
+        <div className={classes.row} key={index}>
+          <div className={classes.item} style={{ flex: 6 }}>
+            <Field label="IP">
+              <Combobox
+                value={listener.ip ?? ""}
+                onChange={(e) => setListener(index, { ip: e.target.value })}
+                onOptionSelect={(_, v) =>
+                  setListener(index, { ip: v.optionText ?? "" })
+                }
+                placeholder="*"
+                appearance="underline"
+                freeform
+              >
+                {["127.0.0.1", "[::]", "0.0.0.0"].map((v) => (
+                  <Option key={v} text={v}>
+                    {v}
+                  </Option>
+                ))}
+              </Combobox>
+            </Field>
+          </div>
+          <div
+            className={classes.row}
             style={{
-              // padding: "0.5rem 0.5rem",
-              lineHeight: "2.3rem",
-              fontWeight: "bold",
-              fontSize: "1.2rem",
+              flex: 2,
+              alignSelf: "flex-end",
+              justifyContent: "center",
             }}
           >
-            :
-          </span>
-        </div>
-        <div className={classes.item} style={{ flex: 4 }}>
-          <Field label="Port">
-            <Input
-              value={config?.port?.toString() ?? ""}
-              onChange={(e) => setPort(Number(e.target.value))}
-              placeholder={config?.enableTls ? "443" : "80"}
-              appearance="underline"
-            />
-          </Field>
+            <span
+              className={classes.item}
+              style={{
+                // padding: "0.5rem 0.5rem",
+                lineHeight: "2.3rem",
+                fontWeight: "bold",
+                fontSize: "1.2rem",
+              }}
+            >
+              :
+            </span>
+          </div>
+          <div className={classes.item} style={{ flex: 4 }}>
+            <Field label="Port">
+              <Input
+                value={listener.port?.toString() ?? ""}
+                onChange={(e) =>
+                  setListener(index, { port: Number(e.target.value) })
+                }
+                placeholder={
+                  listener.enableTls ?? config?.enableTls ? "443" : "80"
+                }
+                appearance="underline"
+              />
+            </Field>
+          </div>
+          <div className={classes.item} style={{ flex: 2 }}>
+            <Field label="TLS">
+              <Switch
+                checked={listener.enableTls ?? config?.enableTls ?? false}
+                onChange={(_, v) =>
+                  setListener(index, { enableTls: v.checked })
+                }
+              />
+            </Field>
+          </div>
+          <div
+            className={classes.item}
+            style={{ flex: 1, alignSelf: "flex-end" }}
+          >
+            {index === 0 ? (
+              <Button
+                icon={<AddRegular />}
+                appearance="transparent"
+                onClick={addListener}
+              />
+            ) : (
+              <Button
+                icon={<DismissRegular />}
+                appearance="transparent"
+                onClick={() => removeListener(index)}
+              />
+            )}
+          </div>
         </div>
-      </div>
+      ))}
 
       <div
         className={classes.row}
@@ -211,7 +377,14 @@ export default function Home() {
           style={{ flex: 8, maxWidth: "75%", flexShrink: 1 }}
         >
           <Field label="Select directory">
-            <Input appearance="underline" value={config?.root ?? ""} />
+            <Input
+              appearance="underline"
+              placeholder="empty to serve mounts only"
+              value={config?.root ?? ""}
+              onChange={(e) =>
+                setConfig({ ...config, root: e.target.value || undefined })
+              }
+            />
           </Field>
         </div>
 
@@ -240,58 +413,296 @@ export default function Home() {
         </div>
       </div>
 
+      <div className={classes.row}>
+        <Checkbox
+          label="read only"
+          checked={config?.readOnly ?? false}
+          onChange={(_, v) => setConfig({ ...config, readOnly: !!v.checked })}
+        />
+        <Checkbox
+          label="keep locks across restarts"
+          checked={config?.persistLocks ?? false}
+          onChange={(_, v) =>
+            setConfig({ ...config, persistLocks: !!v.checked })
+          }
+        />
+        <Button appearance="transparent" icon={<AddRegular />} onClick={addMount}>
+          mount
+        </Button>
+      </div>
+
+      {mounts.map((mount, index) => (
+        <div
+          className={classes.row}
+          key={index}
+          style={{
+            justifyContent: "space-between",
+            alignContent: "stretch",
+            alignItems: "flex-end",
+          }}
+        >
+          <div className={classes.item} style={{ flex: 2, flexShrink: 1 }}>
+            <Field label="path">
+              <Input
+                appearance="underline"
+                placeholder="/projects/"
+                value={mount.path}
+                onChange={(e) => setMount(index, { path: e.target.value })}
+              />
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 4, flexShrink: 1 }}>
+            <Field label="folder">
+              <Input
+                appearance="underline"
+                value={mount.root}
+                onChange={(e) => setMount(index, { root: e.target.value })}
+                contentAfter={
+                  <Button
+                    appearance="transparent"
+                    size="small"
+                    onClick={() => {
+                      dialog
+                        .open({
+                          title: `Select directory of ${mount.path}`,
+                          directory: true,
+                        })
+                        .then((path) => {
+                          if (path) {
+                            setMount(index, { root: path as string });
+                          }
+                        })
+                        .catch(console.error);
+                    }}
+                  >
+                    Browse
+                  </Button>
+                }
+              />
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 2, flexShrink: 1 }}>
+            <Field label="access">
+              <Dropdown
+                appearance="underline"
+                value={mountAccessLabels[mount.access ?? "login"]}
+                selectedOptions={[mount.access ?? "login"]}
+                onOptionSelect={(_, v) =>
+                  setMount(index, { access: v.optionValue as MountAccess })
+                }
+              >
+                {(Object.keys(mountAccessLabels) as MountAccess[]).map((a) => (
+                  <Option key={a} value={a}>
+                    {mountAccessLabels[a]}
+                  </Option>
+                ))}
+              </Dropdown>
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 1, flexShrink: 0 }}>
+            <Checkbox
+              label="read only"
+              checked={mount.readOnly ?? false}
+              onChange={(_, v) => setMount(index, { readOnly: !!v.checked })}
+            />
+          </div>
+
+          <div
+            className={classes.item}
+            style={{ flex: 1, alignSelf: "flex-end" }}
+          >
+            <Button
+              icon={<DismissRegular />}
+              appearance="transparent"
+              onClick={() => removeMount(index)}
+            />
+          </div>
+        </div>
+      ))}
+
+      {users.map((user, index) => (
+        <div
+          className={classes.row}
+          key={index}
+          style={{
+            justifyContent: "space-between",
+            alignContent: "stretch",
+            alignItems: "flex-end",
+          }}
+        >
+          <div className={classes.item} style={{ flex: 3, flexShrink: 1 }}>
+            <Field label="user">
+              <Input
+                appearance="underline"
+                onChange={(e) => setUser(index, { user: e.target.value })}
+                value={user.user}
+              />
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 3, flexShrink: 1 }}>
+            <Field label="password">
+              <Input
+                input={{ className: classes.noEyeSee }}
+                className={showPassword ? "" : classes.noEyeSee}
+                appearance="underline"
+                onChange={(e) => setUser(index, { password: e.target.value })}
+                value={user.password ?? ""}
+                contentAfter={
+                  <Button
+                    icon={showPassword ? <EyeRegular /> : <EyeOffRegular />}
+                    appearance="transparent"
+                    onClick={() => setShowPassword(!showPassword)}
+                  />
+                }
+                type={showPassword ? "text" : "password"}
+              />
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 4, flexShrink: 1 }}>
+            <Field label="folder">
+              <Input
+                appearance="underline"
+                placeholder="shared directory"
+                value={user.root ?? ""}
+                onChange={(e) =>
+                  setUser(index, { root: e.target.value || undefined })
+                }
+                contentAfter={
+                  <Button
+                    appearance="transparent"
+                    size="small"
+                    onClick={() => {
+                      dialog
+                        .open({
+                          title: `Select directory of ${user.user}`,
+                          directory: true,
+                        })
+                        .then((path) => {
+                          if (path) {
+                            setUser(index, { root: path as string });
+                          }
+                        })
+                        .catch(console.error);
+                    }}
+                  >
+                    Browse
+                  </Button>
+                }
+              />
+            </Field>
+          </div>
+
+          <div className={classes.item} style={{ flex: 2, flexShrink: 1 }}>
+            <Field label="access">
+              <Dropdown
+                appearance="underline"
+                value={permissionLabels[user.permission ?? "full"]}
+                selectedOptions={[user.permission ?? "full"]}
+                onOptionSelect={(_, v) =>
+                  setUser(index, { permission: v.optionValue as Permission })
+                }
+              >
+                {(Object.keys(permissionLabels) as Permission[]).map((p) => (
+                  <Option key={p} value={p}>
+                    {permissionLabels[p]}
+                  </Option>
+                ))}
+              </Dropdown>
+            </Field>
+          </div>
+
+          <div
+            className={classes.item}
+            style={{ flex: 1, alignSelf: "flex-end" }}
+          >
+            {index === 0 ? (
+              <Button
+                icon={<AddRegular />}
+                appearance="transparent"
+                onClick={addUser}
+              />
+            ) : (
+              <Button
+                icon={<DismissRegular />}
+                appearance="transparent"
+                onClick={() => removeUser(index)}
+              />
+            )}
+          </div>
+        </div>
+      ))}
+
       <div
         className={classes.row}
         style={{
-          justifyContent: "space-between",
+          justifyContent: "flex-end",
           alignContent: "stretch",
           alignItems: "flex-end",
         }}
       >
+        <div className={classes.item} style={{ flex: 6, flexShrink: 1 }}>
+          <Field label="htpasswd file">
+            <Input
+              appearance="underline"
+              placeholder="users of an Apache htpasswd file"
+              value={htpasswdFile ?? ""}
+              onChange={(e) => setHtpasswdFile(e.target.value || undefined)}
+              contentAfter={
+                <Button
+                  appearance="transparent"
+                  size="small"
+                  onClick={() => {
+                    dialog
+                      .open({ title: "Select htpasswd file" })
+                      .then((path) => {
+                        if (path) {
+                          setHtpasswdFile(path as string);
+                        }
+                      })
+                      .catch(console.error);
+                  }}
+                >
+                  Browse
+                </Button>
+              }
+            />
+          </Field>
+        </div>
         <div
           className={classes.item}
           style={{
             flex: 4,
-            maxWidth: "40%",
-            flexShrink: 1,
+            maxWidth: "10%",
+            flexShrink: 0,
           }}
         >
-          <Field label="user">
-            <Input
-              appearance="underline"
-              onChange={(e) => setUserLocal(e.target.value)}
-              value={userLocal}
-            />
+          <Field label="login">
+            <Switch checked={enableAuth} onChange={enableAuthSwitchCb} />
           </Field>
         </div>
-
         <div
           className={classes.item}
           style={{
             flex: 4,
-            maxWidth: "40%",
-            flexShrink: 1,
+            maxWidth: "10%",
+            flexShrink: 0,
           }}
         >
-          <Field label="password">
-            <Input
-              input={{ className: classes.noEyeSee }}
-              className={showPassword ? "" : classes.noEyeSee}
-              appearance="underline"
-              onChange={(e) => setPasswordLocal(e.target.value)}
-              value={passwordLocal}
-              contentAfter={
-                <Button
-                  icon={showPassword ? <EyeRegular /> : <EyeOffRegular />}
-                  appearance="transparent"
-                  onClick={() => setShowPassword(!showPassword)}
-                />
+          <Field label="guests read">
+            <Switch
+              disabled={!enableAuth}
+              checked={config?.anonymousRead ?? false}
+              onChange={(_, v) =>
+                setConfig({ ...config, anonymousRead: v.checked })
               }
-              type={showPassword ? "text" : "password"}
             />
           </Field>
         </div>
-
         <div
           className={classes.item}
           style={{
@@ -300,18 +711,175 @@ export default function Home() {
             flexShrink: 0,
           }}
         >
-          <Field label="login">
-            <Switch checked={enableAuth} onChange={enableAuthSwitchCb} />
+          <Field label="LAN only">
+            <Switch
+              checked={config?.ipFilter?.privateLanOnly ?? false}
+              onChange={(_, v) =>
+                setConfig({
+                  ...config,
+                  ipFilter: { ...config?.ipFilter, privateLanOnly: v.checked },
+                })
+              }
+            />
           </Field>
         </div>
       </div>
 
+      <div className={classes.row} style={{ alignItems: "flex-end" }}>
+        <div className={classes.item} style={{ flex: 4, flexShrink: 1 }}>
+          <Field label="share folder">
+            <Input
+              appearance="underline"
+              placeholder="folder below the root, e.g. /photos"
+              value={sharePathInput}
+              onChange={(e) => setSharePathInput(e.target.value)}
+            />
+          </Field>
+        </div>
+        <div className={classes.item} style={{ flex: 2, flexShrink: 1 }}>
+          <Field label="scope">
+            <Dropdown
+              appearance="underline"
+              value={shareScopeLabels[shareScope]}
+              selectedOptions={[shareScope]}
+              onOptionSelect={(_, v) =>
+                setShareScope(v.optionValue as ShareScope)
+              }
+            >
+              {(Object.keys(shareScopeLabels) as ShareScope[]).map((s) => (
+                <Option key={s} value={s}>
+                  {shareScopeLabels[s]}
+                </Option>
+              ))}
+            </Dropdown>
+          </Field>
+        </div>
+        <div className={classes.item} style={{ flex: 1, flexShrink: 1 }}>
+          <Field label="days">
+            <Input
+              appearance="underline"
+              type="number"
+              min={1}
+              value={String(shareDays)}
+              onChange={(e) => setShareDays(Number(e.target.value) || 1)}
+            />
+          </Field>
+        </div>
+        <div className={classes.item} style={{ flex: 1, flexShrink: 1 }}>
+          <Field label="max uses">
+            <Input
+              appearance="underline"
+              type="number"
+              min={1}
+              placeholder="unlimited"
+              value={shareMaxUses === undefined ? "" : String(shareMaxUses)}
+              onChange={(e) =>
+                setShareMaxUses(Number(e.target.value) || undefined)
+              }
+            />
+          </Field>
+        </div>
+        <div className={classes.item} style={{ flex: 1 }}>
+          <Button
+            appearance="secondary"
+            onClick={() =>
+              createShare(
+                sharePathInput,
+                Date.now() + shareDays * 24 * 60 * 60 * 1000,
+                shareScope,
+                shareMaxUses
+              )
+                .then((share) => setShares([...shares, share]))
+                .catch(showError)
+            }
+          >
+            Share
+          </Button>
+        </div>
+      </div>
+
+      {shares
+        .filter((share) => !share.revoked && share.expires > Date.now())
+        .map((share) => (
+          <div className={classes.row} key={share.id}>
+            <div className={classes.item} style={{ flex: 6, minWidth: 0 }}>
+              {share.path} ({shareScopeLabels[share.scope]}, until{" "}
+              {new Date(share.expires).toLocaleString()}, used {share.uses}
+              {share.maxUses ? ` of ${share.maxUses}` : ""})
+            </div>
+            <div className={classes.item}>
+              <Button
+                appearance="transparent"
+                size="small"
+                onClick={() =>
+                  navigator.clipboard
+                    .writeText(sharePath(share.token))
+                    .catch(showError)
+                }
+              >
+                Copy link
+              </Button>
+              <Button
+                icon={<DismissRegular />}
+                appearance="transparent"
+                size="small"
+                onClick={() =>
+                  revokeShare(share.id)
+                    .then(() =>
+                      setShares(
+                        shares.map((s) =>
+                          s.id === share.id ? { ...s, revoked: true } : s
+                        )
+                      )
+                    )
+                    .catch(showError)
+                }
+              />
+            </div>
+          </div>
+        ))}
+
       <div
         className={classes.row}
         style={{
           justifyContent: "flex-end",
         }}
       >
+        {failure ? (
+          <div
+            className={classes.item}
+            style={{ color: tokens.colorPaletteRedForeground1 }}
+          >
+            Server failed: {failure}
+          </div>
+        ) : null}
+        {bans.map((ban) => (
+          <div
+            className={classes.item}
+            key={`${ban.target.kind}:${ban.target.value}`}
+            style={{ color: tokens.colorPaletteRedForeground1 }}
+          >
+            {ban.target.kind == "ip" ? "address" : "user"} {ban.target.value}{" "}
+            banned until {new Date(ban.until).toLocaleTimeString()}
+            <Button
+              icon={<DismissRegular />}
+              appearance="transparent"
+              size="small"
+              onClick={() =>
+                clearBan(ban.target)
+                  .then(() => setBans(bans.filter((b) => b !== ban)))
+                  .catch(showError)
+              }
+            />
+          </div>
+        ))}
+        {running ? (
+          <div className={classes.item}>
+            <Button appearance="secondary" onClick={applyConfig}>
+              Apply
+            </Button>
+          </div>
+        ) : null}
         <div className={classes.item}>
           {
             <Button
@@ -359,7 +927,7 @@ export default function Home() {
                       setProcessing(true);
                       updateConfig(config!)
                         .then(() => startDavServer())
-                        .then(() => console.log("server started"))
+                        .then((addr) => console.log("server started at", addr))
                         .then(() => setRunning(true))
                         .catch((err) => {
                           console.log(err);